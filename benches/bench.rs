@@ -1,22 +1,34 @@
-use chess::Board;
 use criterion::{criterion_group, criterion_main, Criterion};
-use pica::{engine::Engine, eval::eval, time::TimeManager, utils::History};
+use pica::{
+    board::Board, engine::Engine, eval::eval, params::EvalParams, pawn_table::PawnTable,
+    position::Position, time::TimeManager, utils::History,
+};
+
+fn search<B: Position>(board: B) {
+    let mut e = Engine::new(64);
+    e.start(
+        board,
+        &TimeManager {
+            max_depth: Some(5),
+            ..Default::default()
+        },
+        History::new(),
+    );
+}
 
 pub fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("eval", |b| b.iter(|| eval(&Board::default())));
-    c.bench_function("search d5", |b| {
-        b.iter(|| {
-            let mut e = Engine::new(64);
-            e.start(
-                Board::default(),
-                &TimeManager {
-                    max_depth: Some(5),
-                    ..Default::default()
-                },
-                History::new(),
-            );
-        });
+    let params = EvalParams::default();
+    let mut pawns = PawnTable::new();
+    let board = Board::default();
+    c.bench_function("eval", |b| b.iter(|| eval(&board, &params, &mut pawns)));
+    c.bench_function("search d5", |b| b.iter(|| search(board)));
+
+    // The same through the `chess` crate adapter
+    let board = chess::Board::default();
+    c.bench_function("eval chess", |b| {
+        b.iter(|| eval(&board, &params, &mut pawns))
     });
+    c.bench_function("search d5 chess", |b| b.iter(|| search(board)));
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,290 @@
+//! Attack tables, with magic bitboards for the sliding pieces
+//! <https://www.chessprogramming.org/Magic_Bitboards>
+
+use const_for::const_for;
+use lazy_static::lazy_static;
+
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Attacks of a piece that moves by fixed offsets of (file, rank)
+const fn leaper_attacks(offsets: &[(i8, i8)]) -> [u64; 64] {
+    let mut attacks = [0; 64];
+
+    const_for!(square in 0..64 => {
+        let (file, rank) = ((square % 8) as i8, (square / 8) as i8);
+        const_for!(i in 0..offsets.len() => {
+            let (f, r) = (file + offsets[i].0, rank + offsets[i].1);
+            if f >= 0 && f < 8 && r >= 0 && r < 8 {
+                attacks[square] |= 1 << (r * 8 + f);
+            }
+        });
+    });
+
+    attacks
+}
+
+pub static KNIGHT_ATTACKS: [u64; 64] = leaper_attacks(&[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+]);
+
+pub static KING_ATTACKS: [u64; 64] = leaper_attacks(&[
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+]);
+
+/// Squares attacked by a pawn, indexed by color and square
+pub static PAWN_ATTACKS: [[u64; 64]; 2] = [
+    leaper_attacks(&[(-1, 1), (1, 1)]),
+    leaper_attacks(&[(-1, -1), (1, -1)]),
+];
+
+/// Attacks of a slider found by walking the rays, used to fill the magic tables
+fn slider_attacks(square: usize, occupied: u64, directions: &[(i8, i8); 4]) -> u64 {
+    let mut attacks = 0;
+
+    for &(df, dr) in directions {
+        let (mut f, mut r) = ((square % 8) as i8 + df, (square / 8) as i8 + dr);
+        while (0..8).contains(&f) && (0..8).contains(&r) {
+            let bit = 1 << (r * 8 + f);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+            f += df;
+            r += dr;
+        }
+    }
+
+    attacks
+}
+
+/// Squares whose occupancy changes the attacks, the last square of every ray does not
+fn relevant_occupancy(square: usize, directions: &[(i8, i8); 4]) -> u64 {
+    let mut mask = 0;
+
+    for &(df, dr) in directions {
+        let (mut f, mut r) = ((square % 8) as i8 + df, (square / 8) as i8 + dr);
+        while (0..8).contains(&(f + df)) && (0..8).contains(&(r + dr)) {
+            mask |= 1 << (r * 8 + f);
+            f += df;
+            r += dr;
+        }
+    }
+
+    mask
+}
+
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    #[inline(always)]
+    const fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct Magics {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+/// Finds a magic for every square by trial, the seed is fixed so this is deterministic
+fn find_magics() -> Magics {
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut random = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut magics = Magics {
+        rook: [Magic::default(); 64],
+        bishop: [Magic::default(); 64],
+        attacks: vec![],
+    };
+
+    for (directions, is_rook) in [(&ROOK_DIRECTIONS, true), (&BISHOP_DIRECTIONS, false)] {
+        for square in 0..64 {
+            let mask = relevant_occupancy(square, directions);
+            let bits = mask.count_ones();
+
+            // All subsets of the mask with the carry rippler trick
+            let mut occupancies = Vec::with_capacity(1 << bits);
+            let mut subset: u64 = 0;
+            loop {
+                occupancies.push((subset, slider_attacks(square, subset, directions)));
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+
+            let mut table = vec![0; 1 << bits];
+            let mut used = vec![0_u32; 1 << bits];
+            let mut attempt = 0;
+            let magic = 'search: loop {
+                let candidate = random() & random() & random();
+                if (mask.wrapping_mul(candidate) >> 56).count_ones() < 6 {
+                    continue;
+                }
+                attempt += 1;
+
+                let magic = Magic {
+                    mask,
+                    magic: candidate,
+                    shift: 64 - bits,
+                    offset: 0,
+                };
+                for &(occupied, attacks) in &occupancies {
+                    let index = magic.index(occupied);
+                    if used[index] != attempt {
+                        used[index] = attempt;
+                        table[index] = attacks;
+                    } else if table[index] != attacks {
+                        continue 'search;
+                    }
+                }
+
+                break Magic {
+                    offset: magics.attacks.len(),
+                    ..magic
+                };
+            };
+
+            magics.attacks.extend(&table);
+            if is_rook {
+                magics.rook[square] = magic;
+            } else {
+                magics.bishop[square] = magic;
+            }
+        }
+    }
+
+    magics
+}
+
+lazy_static! {
+    static ref MAGICS: Magics = find_magics();
+
+    /// Squares strictly between two squares on a line, empty otherwise
+    pub static ref BETWEEN: Vec<[u64; 64]> = {
+        let mut between = vec![[0; 64]; 64];
+        for (a, row) in between.iter_mut().enumerate() {
+            for directions in [&ROOK_DIRECTIONS, &BISHOP_DIRECTIONS] {
+                for (b, entry) in row.iter_mut().enumerate() {
+                    if slider_attacks(a, 0, directions) & (1 << b) != 0 {
+                        *entry = slider_attacks(a, 1 << b, directions)
+                            & slider_attacks(b, 1 << a, directions);
+                    }
+                }
+            }
+        }
+        between
+    };
+
+    /// The whole line through two squares, empty if they are not on one
+    pub static ref LINE: Vec<[u64; 64]> = {
+        let mut line = vec![[0; 64]; 64];
+        for (a, row) in line.iter_mut().enumerate() {
+            for directions in [&ROOK_DIRECTIONS, &BISHOP_DIRECTIONS] {
+                for (b, entry) in row.iter_mut().enumerate() {
+                    if slider_attacks(a, 0, directions) & (1 << b) != 0 {
+                        *entry = (slider_attacks(a, 0, directions)
+                            & slider_attacks(b, 0, directions))
+                            | (1 << a)
+                            | (1 << b);
+                    }
+                }
+            }
+        }
+        line
+    };
+}
+
+#[inline(always)]
+pub fn rook_attacks(square: usize, occupied: u64) -> u64 {
+    MAGICS.attacks[MAGICS.rook[square].index(occupied)]
+}
+
+#[inline(always)]
+pub fn bishop_attacks(square: usize, occupied: u64) -> u64 {
+    MAGICS.attacks[MAGICS.bishop[square].index(occupied)]
+}
+
+#[inline(always)]
+pub fn queen_attacks(square: usize, occupied: u64) -> u64 {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+mod test {
+    #[test]
+    fn test_attacks() {
+        use super::*;
+        use chess::{BitBoard, Square, ALL_SQUARES};
+
+        // Compare with the `chess` crate on pseudo random occupancies
+        let mut state: u64 = 0x1234_5678_9ABC_DEF1;
+        for _ in 0..200 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let occupied = state & (state >> 3);
+
+            for square in ALL_SQUARES {
+                let i = square.to_index();
+                assert_eq!(
+                    rook_attacks(i, occupied),
+                    chess::get_rook_moves(square, BitBoard(occupied)).0
+                );
+                assert_eq!(
+                    bishop_attacks(i, occupied),
+                    chess::get_bishop_moves(square, BitBoard(occupied)).0
+                );
+            }
+        }
+
+        for square in ALL_SQUARES {
+            let i = square.to_index();
+            assert_eq!(KNIGHT_ATTACKS[i], chess::get_knight_moves(square).0);
+            assert_eq!(KING_ATTACKS[i], chess::get_king_moves(square).0);
+            for color in [chess::Color::White, chess::Color::Black] {
+                assert_eq!(
+                    PAWN_ATTACKS[color.to_index()][i],
+                    chess::get_pawn_attacks(square, color, !chess::EMPTY).0
+                );
+            }
+        }
+
+        assert_eq!(
+            BETWEEN[Square::A1.to_index()][Square::D4.to_index()],
+            BitBoard::from_square(Square::B2).0 | BitBoard::from_square(Square::C3).0
+        );
+        assert_eq!(BETWEEN[Square::A1.to_index()][Square::B3.to_index()], 0);
+        assert_eq!(
+            LINE[Square::B2.to_index()][Square::C3.to_index()],
+            chess::line(Square::B2, Square::C3).0
+        );
+    }
+}
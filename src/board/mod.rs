@@ -0,0 +1,847 @@
+//! Native board representation
+//! <https://www.chessprogramming.org/Bitboard_Board-Definition>
+//!
+//! Bitboards plus a mailbox, made and unmade in place with the Zobrist key and the
+//! material + PST score updated incrementally. Moves and squares use the `chess` crate types,
+//! castling is encoded as the king taking its own rook so Chess960 needs no special cases.
+
+mod attacks;
+mod movegen;
+pub mod psqt;
+mod zobrist;
+
+use std::fmt;
+use std::str::FromStr;
+
+use chess::{BitBoard, CastleRights, ChessMove, Color, File, Piece, Rank, Square, ALL_SQUARES};
+
+use attacks::{bishop_attacks, rook_attacks, KING_ATTACKS, KNIGHT_ATTACKS, PAWN_ATTACKS};
+pub use movegen::MoveList;
+use psqt::psqt;
+use zobrist::KEYS;
+
+#[inline(always)]
+const fn bit(square: usize) -> u64 {
+    1 << square
+}
+
+#[inline(always)]
+fn square(index: usize) -> Square {
+    ALL_SQUARES[index]
+}
+
+/// Index of the castling side, the rook is on the king's right on the kingside
+const KINGSIDE: usize = 0;
+const QUEENSIDE: usize = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Board {
+    pieces: [BitBoard; 6],
+    colors: [BitBoard; 2],
+    combined: BitBoard,
+    mailbox: [Option<Piece>; 64],
+    side_to_move: Color,
+    /// Square of the castling rook, indexed by color and side
+    castling: [[Option<Square>; 2]; 2],
+    /// Only set when a pawn can capture there
+    en_passant: Option<Square>,
+    halfmove: u16,
+    fullmove: u16,
+    hash: u64,
+    checkers: BitBoard,
+    /// Material + PST from white's point of view, (middlegame, endgame)
+    psqt: (i32, i32),
+}
+
+/// What `unmake_move` needs to restore
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    captured: Option<Piece>,
+    castle: bool,
+    castling: [[Option<Square>; 2]; 2],
+    en_passant: Option<Square>,
+    halfmove: u16,
+    hash: u64,
+    checkers: BitBoard,
+    psqt: (i32, i32),
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+            .expect("valid start position")
+    }
+}
+
+impl Board {
+    fn empty() -> Self {
+        Self {
+            pieces: [chess::EMPTY; 6],
+            colors: [chess::EMPTY; 2],
+            combined: chess::EMPTY,
+            mailbox: [None; 64],
+            side_to_move: Color::White,
+            castling: [[None; 2]; 2],
+            en_passant: None,
+            halfmove: 0,
+            fullmove: 1,
+            hash: 0,
+            checkers: chess::EMPTY,
+            psqt: (0, 0),
+        }
+    }
+
+    /// Parses a FEN with standard, Shredder or X-FEN castling rights
+    /// # Errors
+    /// when the FEN is invalid
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!("fen `{fen}` has less than 4 fields"));
+        }
+        let mut board = Self::empty();
+
+        // Pieces, from a8 to h1
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("fen `{fen}` does not have 8 ranks"));
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let mut file = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    file += skip as usize;
+                    continue;
+                }
+                let piece = match c.to_ascii_lowercase() {
+                    'p' => Piece::Pawn,
+                    'n' => Piece::Knight,
+                    'b' => Piece::Bishop,
+                    'r' => Piece::Rook,
+                    'q' => Piece::Queen,
+                    'k' => Piece::King,
+                    _ => return Err(format!("invalid piece `{c}` in `{fen}`")),
+                };
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                if file >= 8 {
+                    return Err(format!("rank `{rank}` is too long in `{fen}`"));
+                }
+                board.put(piece, color, (7 - i) * 8 + file);
+                file += 1;
+            }
+            if file != 8 {
+                return Err(format!("rank `{rank}` is not 8 squares in `{fen}`"));
+            }
+        }
+        for color in [Color::White, Color::Black] {
+            if (board.bb(Piece::King) & board.us(color)).count_ones() != 1 {
+                return Err(format!("fen `{fen}` needs one king of each color"));
+            }
+        }
+
+        board.side_to_move = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            x => return Err(format!("invalid side to move `{x}` in `{fen}`")),
+        };
+
+        for c in fields[2].chars().filter(|&c| c != '-') {
+            let color = if c.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let king = board.king_square(color);
+            let back_rank = color.to_my_backrank();
+            if king.get_rank() != back_rank {
+                return Err(format!(
+                    "castling without a king on the back rank in `{fen}`"
+                ));
+            }
+            let rooks =
+                BitBoard(board.bb(Piece::Rook) & board.us(color) & chess::get_rank(back_rank).0);
+            let rook = match c.to_ascii_lowercase() {
+                'k' => rooks.filter(|sq| sq.get_file() > king.get_file()).last(),
+                'q' => rooks.into_iter().find(|sq| sq.get_file() < king.get_file()),
+                f @ 'a'..='h' => rooks
+                    .into_iter()
+                    .find(|sq| sq.get_file().to_index() == f as usize - 'a' as usize),
+                _ => None,
+            };
+            let Some(rook) = rook else {
+                return Err(format!("invalid castling right `{c}` in `{fen}`"));
+            };
+            let side = if rook.get_file() > king.get_file() {
+                KINGSIDE
+            } else {
+                QUEENSIDE
+            };
+            board.castling[color.to_index()][side] = Some(rook);
+        }
+
+        if fields[3] != "-" {
+            let ep: Square = fields[3]
+                .parse()
+                .map_err(|_| format!("invalid en passant square in `{fen}`"))?;
+            board.set_en_passant(ep.to_index(), board.side_to_move);
+        }
+
+        board.halfmove = fields
+            .get(4)
+            .map_or(Ok(0), |x| x.parse())
+            .map_err(|_| format!("invalid halfmove clock in `{fen}`"))?;
+        board.fullmove = fields
+            .get(5)
+            .map_or(Ok(1), |x| x.parse())
+            .map_err(|_| format!("invalid fullmove number in `{fen}`"))?;
+
+        board.hash ^= KEYS.castling[board.castling_mask()];
+        if board.side_to_move == Color::Black {
+            board.hash ^= KEYS.side;
+        }
+        board.checkers = board.compute_checkers();
+
+        // The side that just moved cannot be in check
+        let them = !board.side_to_move;
+        if board.attackers_to(board.king_square(them).to_index(), board.occupied())
+            & board.us(board.side_to_move)
+            != 0
+        {
+            return Err(format!("the side not to move is in check in `{fen}`"));
+        }
+
+        Ok(board)
+    }
+
+    /// A position without castling rights or en passant from a list of pieces
+    /// # Errors
+    /// when the pieces overlap, there is not one king of each color, pawns are on the back
+    /// ranks or the side not to move is in check
+    pub fn from_pieces(
+        pieces: &[(Piece, Color, Square)],
+        side_to_move: Color,
+    ) -> Result<Self, String> {
+        let mut board = Self::empty();
+
+        for &(piece, color, square) in pieces {
+            if board.occupied() & bit(square.to_index()) != 0 {
+                return Err(format!("two pieces on {square}"));
+            }
+            if piece == Piece::Pawn && matches!(square.get_rank(), Rank::First | Rank::Eighth) {
+                return Err(format!("pawn on {square}"));
+            }
+            board.put(piece, color, square.to_index());
+        }
+        for color in [Color::White, Color::Black] {
+            if (board.bb(Piece::King) & board.us(color)).count_ones() != 1 {
+                return Err("needs one king of each color".to_string());
+            }
+        }
+
+        board.side_to_move = side_to_move;
+        if side_to_move == Color::Black {
+            board.hash ^= KEYS.side;
+        }
+        board.hash ^= KEYS.castling[0];
+        board.checkers = board.compute_checkers();
+
+        let them = !side_to_move;
+        if board.attackers_to(board.king_square(them).to_index(), board.occupied())
+            & board.us(side_to_move)
+            != 0
+        {
+            return Err("the side not to move is in check".to_string());
+        }
+
+        Ok(board)
+    }
+
+    /// Places a piece on an empty square
+    #[inline(always)]
+    fn put(&mut self, piece: Piece, color: Color, square: usize) {
+        self.pieces[piece.to_index()].0 |= bit(square);
+        self.colors[color.to_index()].0 |= bit(square);
+        self.combined.0 |= bit(square);
+        self.mailbox[square] = Some(piece);
+        self.hash ^= KEYS.pieces[color.to_index()][piece.to_index()][square];
+
+        let (mg, eg) = psqt(piece, color, square);
+        self.psqt = (self.psqt.0 + mg, self.psqt.1 + eg);
+    }
+
+    /// Removes the piece on an occupied square
+    #[inline(always)]
+    fn remove(&mut self, square: usize) -> Piece {
+        let piece = self.mailbox[square].expect("remove from an empty square");
+        let color = if self.us(Color::White) & bit(square) != 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        self.pieces[piece.to_index()].0 ^= bit(square);
+        self.colors[color.to_index()].0 ^= bit(square);
+        self.combined.0 ^= bit(square);
+        self.mailbox[square] = None;
+        self.hash ^= KEYS.pieces[color.to_index()][piece.to_index()][square];
+
+        let (mg, eg) = psqt(piece, color, square);
+        self.psqt = (self.psqt.0 - mg, self.psqt.1 - eg);
+        piece
+    }
+
+    /// Sets the en passant square if a pawn of `by` can capture there
+    fn set_en_passant(&mut self, square: usize, by: Color) {
+        let pawns = self.bb(Piece::Pawn) & self.us(by);
+        if PAWN_ATTACKS[(!by).to_index()][square] & pawns != 0 {
+            self.en_passant = Some(self::square(square));
+            self.hash ^= KEYS.en_passant[square % 8];
+        }
+    }
+
+    /// The castling rights as 4 bits, white kingside first
+    fn castling_mask(&self) -> usize {
+        let mut mask = 0;
+        for (i, rook) in self.castling.iter().flatten().enumerate() {
+            if rook.is_some() {
+                mask |= 1 << i;
+            }
+        }
+        mask
+    }
+
+    #[inline(always)]
+    fn occupied(&self) -> u64 {
+        self.combined.0
+    }
+
+    #[inline(always)]
+    fn bb(&self, piece: Piece) -> u64 {
+        self.pieces[piece.to_index()].0
+    }
+
+    #[inline(always)]
+    fn us(&self, color: Color) -> u64 {
+        self.colors[color.to_index()].0
+    }
+
+    /// Pieces of both colors attacking `square` with the given occupancy
+    #[inline(always)]
+    fn attackers_to(&self, square: usize, occupied: u64) -> u64 {
+        let queens = self.bb(Piece::Queen);
+
+        (PAWN_ATTACKS[Color::White.to_index()][square]
+            & self.bb(Piece::Pawn)
+            & self.us(Color::Black))
+            | (PAWN_ATTACKS[Color::Black.to_index()][square]
+                & self.bb(Piece::Pawn)
+                & self.us(Color::White))
+            | (KNIGHT_ATTACKS[square] & self.bb(Piece::Knight))
+            | (KING_ATTACKS[square] & self.bb(Piece::King))
+            | (bishop_attacks(square, occupied) & (self.bb(Piece::Bishop) | queens))
+            | (rook_attacks(square, occupied) & (self.bb(Piece::Rook) | queens))
+    }
+
+    fn compute_checkers(&self) -> BitBoard {
+        let us = self.side_to_move;
+        BitBoard(self.attackers_to(self.king_square(us).to_index(), self.occupied()) & self.us(!us))
+    }
+
+    #[must_use]
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    #[must_use]
+    pub fn piece_on(&self, square: Square) -> Option<Piece> {
+        self.mailbox[square.to_index()]
+    }
+
+    #[must_use]
+    pub fn color_on(&self, square: Square) -> Option<Color> {
+        if self.us(Color::White) & bit(square.to_index()) != 0 {
+            Some(Color::White)
+        } else if self.us(Color::Black) & bit(square.to_index()) != 0 {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    #[must_use]
+    pub fn pieces(&self, piece: Piece) -> &BitBoard {
+        &self.pieces[piece.to_index()]
+    }
+
+    #[must_use]
+    pub fn color_combined(&self, color: Color) -> &BitBoard {
+        &self.colors[color.to_index()]
+    }
+
+    #[must_use]
+    pub fn combined(&self) -> &BitBoard {
+        &self.combined
+    }
+
+    #[must_use]
+    pub fn king_square(&self, color: Color) -> Square {
+        square((self.bb(Piece::King) & self.us(color)).trailing_zeros() as usize)
+    }
+
+    #[must_use]
+    pub fn checkers(&self) -> &BitBoard {
+        &self.checkers
+    }
+
+    #[must_use]
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
+    #[must_use]
+    pub fn en_passant(&self) -> Option<Square> {
+        self.en_passant
+    }
+
+    #[must_use]
+    pub fn halfmove_clock(&self) -> u16 {
+        self.halfmove
+    }
+
+    #[must_use]
+    pub fn fullmove_number(&self) -> u16 {
+        self.fullmove
+    }
+
+    /// Square of the rook `color` may still castle with
+    #[must_use]
+    pub fn castle_rook(&self, color: Color, kingside: bool) -> Option<Square> {
+        self.castling[color.to_index()][if kingside { KINGSIDE } else { QUEENSIDE }]
+    }
+
+    /// Castling rights in the `chess` crate's terms
+    #[must_use]
+    pub fn castle_rights(&self, color: Color) -> CastleRights {
+        match (
+            self.castle_rook(color, true),
+            self.castle_rook(color, false),
+        ) {
+            (Some(_), Some(_)) => CastleRights::Both,
+            (Some(_), None) => CastleRights::KingSide,
+            (None, Some(_)) => CastleRights::QueenSide,
+            (None, None) => CastleRights::NoRights,
+        }
+    }
+
+    /// Material + PST from white's point of view, (middlegame, endgame)
+    #[must_use]
+    pub fn psqt(&self) -> (i32, i32) {
+        self.psqt
+    }
+
+    /// Adds up the material + PST score again, after `psqt::set_params` changed the table
+    pub fn refresh_psqt(&mut self) {
+        self.psqt = (0, 0);
+        for (index, piece) in self.mailbox.into_iter().enumerate() {
+            if let (Some(piece), Some(color)) = (piece, self.color_on(square(index))) {
+                let (mg, eg) = psqt(piece, color, index);
+                self.psqt = (self.psqt.0 + mg, self.psqt.1 + eg);
+            }
+        }
+    }
+
+    /// Whether the move is castling, which is written as the king taking its own rook
+    #[must_use]
+    pub fn is_castling(&self, mv: ChessMove) -> bool {
+        self.mailbox[mv.get_source().to_index()] == Some(Piece::King)
+            && self.us(self.side_to_move) & bit(mv.get_dest().to_index()) != 0
+    }
+
+    /// Whether the move takes a piece, including en passant but not castling
+    #[must_use]
+    pub fn is_capture(&self, mv: ChessMove) -> bool {
+        self.us(!self.side_to_move) & bit(mv.get_dest().to_index()) != 0
+            || (Some(mv.get_dest()) == self.en_passant
+                && self.mailbox[mv.get_source().to_index()] == Some(Piece::Pawn))
+    }
+
+    /// Plays a legal move
+    pub fn make_move(&mut self, mv: ChessMove) -> Undo {
+        let us = self.side_to_move;
+        let (source, dest) = (mv.get_source().to_index(), mv.get_dest().to_index());
+        let piece = self.mailbox[source].expect("no piece on move source");
+        let mut undo = Undo {
+            captured: None,
+            castle: false,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove: self.halfmove,
+            hash: self.hash,
+            checkers: self.checkers,
+            psqt: self.psqt,
+        };
+
+        if let Some(ep) = self.en_passant.take() {
+            self.hash ^= KEYS.en_passant[ep.get_file().to_index()];
+        }
+        self.hash ^= KEYS.castling[self.castling_mask()];
+        self.halfmove += 1;
+
+        if self.is_castling(mv) {
+            // The king ends up on the g- or c-file and the rook next to it
+            let base = source & !7;
+            let (king, rook) = if dest > source {
+                (base + 6, base + 5)
+            } else {
+                (base + 2, base + 3)
+            };
+            self.remove(source);
+            self.remove(dest);
+            self.put(Piece::King, us, king);
+            self.put(Piece::Rook, us, rook);
+            self.castling[us.to_index()] = [None; 2];
+            undo.castle = true;
+        } else {
+            if self.mailbox[dest].is_some() {
+                undo.captured = Some(self.remove(dest));
+                self.halfmove = 0;
+            }
+
+            if piece == Piece::Pawn {
+                self.halfmove = 0;
+                if Some(dest) == undo.en_passant.map(|sq| sq.to_index()) {
+                    // The captured pawn is behind the destination
+                    undo.captured = Some(self.remove(dest ^ 8));
+                }
+            }
+
+            self.remove(source);
+            self.put(mv.get_promotion().unwrap_or(piece), us, dest);
+
+            if piece == Piece::Pawn && source ^ dest == 16 {
+                self.set_en_passant((source + dest) / 2, !us);
+            }
+
+            // Moving the king or a rook, or capturing a rook, loses the right
+            if piece == Piece::King {
+                self.castling[us.to_index()] = [None; 2];
+            }
+            for rook in self.castling.iter_mut().flatten() {
+                if *rook == Some(square(source)) || *rook == Some(square(dest)) {
+                    *rook = None;
+                }
+            }
+        }
+
+        self.hash ^= KEYS.castling[self.castling_mask()];
+        if us == Color::Black {
+            self.fullmove += 1;
+        }
+        self.side_to_move = !us;
+        self.hash ^= KEYS.side;
+        self.checkers = self.compute_checkers();
+
+        undo
+    }
+
+    /// Takes back the last move played with `make_move`
+    pub fn unmake_move(&mut self, mv: ChessMove, undo: &Undo) {
+        let us = !self.side_to_move;
+        let (source, dest) = (mv.get_source().to_index(), mv.get_dest().to_index());
+
+        if undo.castle {
+            let base = source & !7;
+            let (king, rook) = if dest > source {
+                (base + 6, base + 5)
+            } else {
+                (base + 2, base + 3)
+            };
+            self.remove(king);
+            self.remove(rook);
+            self.put(Piece::King, us, source);
+            self.put(Piece::Rook, us, dest);
+        } else {
+            let moved = self.remove(dest);
+            let piece = if mv.get_promotion().is_some() {
+                Piece::Pawn
+            } else {
+                moved
+            };
+            self.put(piece, us, source);
+
+            if let Some(captured) = undo.captured {
+                if piece == Piece::Pawn && Some(dest) == undo.en_passant.map(|sq| sq.to_index()) {
+                    self.put(captured, !us, dest ^ 8);
+                } else {
+                    self.put(captured, !us, dest);
+                }
+            }
+        }
+
+        if us == Color::Black {
+            self.fullmove -= 1;
+        }
+        self.side_to_move = us;
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove = undo.halfmove;
+        self.hash = undo.hash;
+        self.checkers = undo.checkers;
+        self.psqt = undo.psqt;
+    }
+
+    /// Returns the board after a legal move
+    #[must_use]
+    pub fn make_move_new(&self, mv: ChessMove) -> Self {
+        let mut board = *self;
+        board.make_move(mv);
+        board
+    }
+
+    /// Writes a move in UCI notation, castling as king takes rook in Chess960
+    #[must_use]
+    pub fn format_move(&self, mv: ChessMove, chess960: bool) -> String {
+        if chess960 || !self.is_castling(mv) {
+            return mv.to_string();
+        }
+
+        let file = if mv.get_dest() > mv.get_source() {
+            File::G
+        } else {
+            File::C
+        };
+        let king = Square::make_square(mv.get_source().get_rank(), file);
+        format!("{}{king}", mv.get_source())
+    }
+
+    /// Finds the legal move written in UCI notation, castling in either notation
+    #[must_use]
+    pub fn parse_uci(&self, text: &str) -> Option<ChessMove> {
+        self.legal_moves()
+            .into_iter()
+            .find(|&mv| self.format_move(mv, false) == text || self.format_move(mv, true) == text)
+    }
+}
+
+impl fmt::Display for Board {
+    /// FEN, with X-FEN castling rights
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let sq = rank * 8 + file;
+                let Some(piece) = self.mailbox[sq] else {
+                    empty += 1;
+                    continue;
+                };
+                if empty > 0 {
+                    write!(f, "{empty}")?;
+                    empty = 0;
+                }
+                let color = if self.us(Color::White) & bit(sq) != 0 {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                write!(f, "{}", piece.to_string(color))?;
+            }
+            if empty > 0 {
+                write!(f, "{empty}")?;
+            }
+            if rank > 0 {
+                write!(f, "/")?;
+            }
+        }
+
+        let side = if self.side_to_move == Color::White {
+            "w"
+        } else {
+            "b"
+        };
+        write!(f, " {side} ")?;
+
+        let mut castling = String::new();
+        for color in [Color::White, Color::Black] {
+            let rooks =
+                self.bb(Piece::Rook) & self.us(color) & chess::get_rank(color.to_my_backrank()).0;
+            for (side, letter) in [(KINGSIDE, 'k'), (QUEENSIDE, 'q')] {
+                let Some(rook) = self.castling[color.to_index()][side] else {
+                    continue;
+                };
+                // K or Q when it is the outermost rook, the file otherwise
+                let outer = if side == KINGSIDE {
+                    rooks >> rook.to_index() == 1
+                } else {
+                    rooks & (bit(rook.to_index()) - 1) == 0
+                };
+                let c = if outer {
+                    letter
+                } else {
+                    (b'a' + rook.get_file().to_index() as u8) as char
+                };
+                castling.push(if color == Color::White {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                });
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let ep = self.en_passant.map_or("-".to_string(), |x| x.to_string());
+        write!(f, "{castling} {ep} {} {}", self.halfmove, self.fullmove)
+    }
+}
+
+impl FromStr for Board {
+    type Err = String;
+
+    fn from_str(fen: &str) -> Result<Self, Self::Err> {
+        Self::from_fen(fen)
+    }
+}
+
+impl From<&chess::Board> for Board {
+    fn from(board: &chess::Board) -> Self {
+        // The `chess` crate writes the square of the pawn that can be taken en passant
+        let fen = board.to_string();
+        let mut fields: Vec<&str> = fen.split(' ').collect();
+        fields[3] = "-";
+        let mut native =
+            Self::from_fen(&fields.join(" ")).expect("the chess crate writes valid fens");
+        if let Some(pawn) = board.en_passant() {
+            native.set_en_passant(pawn.to_index() ^ 8, native.side_to_move);
+        }
+        native
+    }
+}
+
+/// Everything that `make_move` updates incrementally, computed from scratch
+#[cfg(test)]
+fn from_scratch(board: &Board) -> (u64, (i32, i32)) {
+    let mut hash = KEYS.castling[board.castling_mask()];
+    let mut score = (0, 0);
+    for piece in chess::ALL_PIECES {
+        for color in [Color::White, Color::Black] {
+            for sq in *board.pieces(piece) & *board.color_combined(color) {
+                hash ^= KEYS.pieces[color.to_index()][piece.to_index()][sq.to_index()];
+                let (mg, eg) = psqt(piece, color, sq.to_index());
+                score = (score.0 + mg, score.1 + eg);
+            }
+        }
+    }
+    if let Some(ep) = board.en_passant {
+        hash ^= KEYS.en_passant[ep.get_file().to_index()];
+    }
+    if board.side_to_move == Color::Black {
+        hash ^= KEYS.side;
+    }
+    (hash, score)
+}
+mod test {
+    #[test]
+    fn test_fen() {
+        use super::*;
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+            "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/RR2K1R1 w KBk - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(board.to_string(), fen);
+            assert_eq!(from_scratch(&board), (board.hash, board.psqt));
+        }
+
+        // Shredder-FEN is written as X-FEN, and en passant only when it is possible
+        let board =
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b HAha e3 0 1").unwrap();
+        assert_eq!(
+            board.to_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        let chess =
+            chess::Board::default().make_move_new(ChessMove::new(Square::E2, Square::E4, None));
+        assert_eq!(Board::from(&chess), board);
+
+        assert!(Board::from_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1").is_err());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K2R w X - 0 1").is_err());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4Q1K1 w - - 0 1").is_err());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w -").is_err());
+    }
+
+    #[test]
+    fn test_psqt() {
+        use super::*;
+        use crate::eval::{eval_trace, Term};
+        use crate::params::EvalParams;
+
+        // The incremental score is the material + PST term of the eval with the default params
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        let trace = eval_trace(&board, &EvalParams::default());
+        assert_eq!(board.psqt(), trace.total(Term::Material));
+    }
+
+    #[test]
+    fn test_make_unmake() {
+        use super::*;
+        use std::str::FromStr;
+
+        // Random games, checking the incremental updates against the `chess` crate
+        let mut state: u64 = 0x0123_4567_89AB_CDEF;
+        for _ in 0..20 {
+            let mut board = Board::default();
+            let mut chess = chess::Board::default();
+
+            for _ in 0..200 {
+                let moves = board.legal_moves();
+                let mut expected: Vec<String> = chess::MoveGen::new_legal(&chess)
+                    .map(|mv| mv.to_string())
+                    .collect();
+                let mut actual: Vec<String> = moves
+                    .iter()
+                    .map(|&mv| board.format_move(mv, false))
+                    .collect();
+                expected.sort();
+                actual.sort();
+                assert_eq!(actual, expected, "{board}");
+                if moves.is_empty() {
+                    break;
+                }
+
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let mv = moves[(state % moves.len() as u64) as usize];
+
+                let before = board;
+                let undo = board.make_move(mv);
+                assert_eq!(from_scratch(&board), (board.hash, board.psqt), "{board}");
+                board.unmake_move(mv, &undo);
+                assert_eq!(board, before);
+
+                let uci = board.format_move(mv, false);
+                chess = chess.make_move_new(chess::ChessMove::from_str(&uci).unwrap());
+                board.make_move(mv);
+                assert_eq!(
+                    board.to_string().split(' ').take(4).collect::<Vec<_>>(),
+                    Board::from(&chess)
+                        .to_string()
+                        .split(' ')
+                        .take(4)
+                        .collect::<Vec<_>>()
+                );
+                assert_eq!(board.checkers().0, chess.checkers().0);
+            }
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Legal move generation with pin and check masks
+//! <https://www.chessprogramming.org/Move_Generation#Legal>
+
+use arrayvec::ArrayVec;
+use chess::{BitBoard, BoardStatus, ChessMove, Color, Piece};
+
+use super::attacks::{
+    bishop_attacks, queen_attacks, rook_attacks, BETWEEN, KING_ATTACKS, KNIGHT_ATTACKS, LINE,
+    PAWN_ATTACKS,
+};
+use super::{bit, square, Board, KINGSIDE, QUEENSIDE};
+
+/// Enough for any legal position
+pub type MoveList = ArrayVec<ChessMove, 256>;
+
+const PROMOTIONS: [Piece; 4] = [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop];
+
+impl Board {
+    /// Pieces of the side to move that are pinned to their king
+    #[must_use]
+    pub fn pinned(&self) -> BitBoard {
+        let us = self.side_to_move;
+        let king = self.king_square(us).to_index();
+        let queens = self.bb(Piece::Queen);
+
+        let snipers = (rook_attacks(king, 0) & (self.bb(Piece::Rook) | queens)
+            | bishop_attacks(king, 0) & (self.bb(Piece::Bishop) | queens))
+            & self.us(!us);
+
+        let mut pinned = 0;
+        for sniper in BitBoard(snipers) {
+            let between = BETWEEN[king][sniper.to_index()] & self.occupied();
+            if between.count_ones() == 1 {
+                pinned |= between & self.us(us);
+            }
+        }
+        BitBoard(pinned)
+    }
+
+    /// Whether the king of the side to move would be attacked on `square`
+    fn king_attacked(&self, square: usize, occupied: u64) -> bool {
+        self.attackers_to(square, occupied) & self.us(!self.side_to_move) != 0
+    }
+
+    /// All legal moves
+    #[must_use]
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate(&mut moves, true, true);
+        moves
+    }
+
+    /// Legal captures, capturing promotions and en passant
+    pub fn generate_captures(&self, moves: &mut MoveList) {
+        self.generate(moves, true, false);
+    }
+
+    /// Legal non captures, including quiet promotions and castling
+    pub fn generate_quiets(&self, moves: &mut MoveList) {
+        self.generate(moves, false, true);
+    }
+
+    /// Whether there is a legal move, usually without generating them
+    #[must_use]
+    pub fn has_legal_moves(&self) -> bool {
+        let king = self.king_square(self.side_to_move).to_index();
+        let occupied = self.occupied() ^ bit(king);
+
+        BitBoard(KING_ATTACKS[king] & !self.us(self.side_to_move))
+            .any(|dest| !self.king_attacked(dest.to_index(), occupied))
+            || !self.legal_moves().is_empty()
+    }
+
+    /// Whether a move, that might come from another position, is legal
+    #[must_use]
+    pub fn legal(&self, mv: ChessMove) -> bool {
+        self.legal_moves().contains(&mv)
+    }
+
+    #[must_use]
+    pub fn status(&self) -> BoardStatus {
+        if self.has_legal_moves() {
+            BoardStatus::Ongoing
+        } else if self.checkers.0 != 0 {
+            BoardStatus::Checkmate
+        } else {
+            BoardStatus::Stalemate
+        }
+    }
+
+    /// Pawns, knights, bishops, rooks, queens and the king, each from a1 to h8 with their
+    /// destinations from a1 to h8, the order of the `chess` crate.
+    /// Moves that score the same in `MoveGenOrdered` are searched in this order.
+    fn generate(&self, moves: &mut MoveList, captures: bool, quiets: bool) {
+        let us = self.side_to_move;
+        let occupied = self.occupied();
+        let king = self.king_square(us).to_index();
+        let checkers = self.checkers.0;
+
+        let mut targets = 0;
+        if captures {
+            targets |= self.us(!us);
+        }
+        if quiets {
+            targets |= !occupied;
+        }
+
+        // Only the king can move out of a double check
+        if checkers.count_ones() < 2 {
+            // Captures of the checker or blocks
+            let check_mask = if checkers == 0 {
+                !0
+            } else {
+                BETWEEN[king][checkers.trailing_zeros() as usize] | checkers
+            };
+            let pinned = self.pinned().0;
+
+            // Pinned pieces stay on the line through their king
+            let allowed = |from: usize| {
+                check_mask
+                    & if pinned & bit(from) == 0 {
+                        !0
+                    } else {
+                        LINE[king][from]
+                    }
+            };
+
+            self.generate_pawns(moves, captures, quiets, &allowed);
+
+            for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+                for source in BitBoard(self.bb(piece) & self.us(us)) {
+                    let from = source.to_index();
+                    let attacks = match piece {
+                        Piece::Knight => KNIGHT_ATTACKS[from],
+                        Piece::Bishop => bishop_attacks(from, occupied),
+                        Piece::Rook => rook_attacks(from, occupied),
+                        _ => queen_attacks(from, occupied),
+                    };
+                    for dest in BitBoard(attacks & targets & allowed(from)) {
+                        moves.push(ChessMove::new(source, dest, None));
+                    }
+                }
+            }
+        }
+
+        // The king itself does not block attacks on the squares behind it
+        let mut dests = 0;
+        for dest in BitBoard(KING_ATTACKS[king] & targets) {
+            if !self.king_attacked(dest.to_index(), occupied ^ bit(king)) {
+                dests |= bit(dest.to_index());
+            }
+        }
+        if quiets && checkers == 0 {
+            dests |= self.castling_rooks();
+        }
+        for dest in BitBoard(dests) {
+            moves.push(ChessMove::new(square(king), dest, None));
+        }
+    }
+
+    fn generate_pawns(
+        &self,
+        moves: &mut MoveList,
+        captures: bool,
+        quiets: bool,
+        allowed: &impl Fn(usize) -> u64,
+    ) {
+        let us = self.side_to_move;
+        let theirs = self.us(!us);
+        let occupied = self.occupied();
+        let (forward, start_rank, last_rank): (i32, u64, u64) = match us {
+            Color::White => (8, 0xFF00, 0xFF << 56),
+            Color::Black => (-8, 0xFF << 48, 0xFF),
+        };
+
+        for source in BitBoard(self.bb(Piece::Pawn) & self.us(us)) {
+            let from = source.to_index();
+            let mut dests = 0;
+
+            let one = (from as i32 + forward) as usize;
+            if quiets && occupied & bit(one) == 0 {
+                dests |= bit(one);
+                let two = (one as i32 + forward) as usize;
+                if bit(from) & start_rank != 0 && occupied & bit(two) == 0 {
+                    dests |= bit(two);
+                }
+            }
+
+            let attacks = PAWN_ATTACKS[us.to_index()][from];
+            if captures {
+                dests |= attacks & theirs;
+            }
+            dests &= allowed(from);
+
+            // En passant can uncover the king along the rank, so try it on the board
+            if let (true, Some(ep)) = (captures, self.en_passant) {
+                let to = ep.to_index();
+                if attacks & bit(to) != 0 {
+                    let captured = to ^ 8;
+                    let after = occupied ^ bit(from) ^ bit(captured) | bit(to);
+                    let king = self.king_square(us).to_index();
+                    if self.attackers_to(king, after) & theirs & !bit(captured) == 0 {
+                        dests |= bit(to);
+                    }
+                }
+            }
+
+            for dest in BitBoard(dests) {
+                if bit(dest.to_index()) & last_rank == 0 {
+                    moves.push(ChessMove::new(source, dest, None));
+                } else {
+                    for piece in PROMOTIONS {
+                        moves.push(ChessMove::new(source, dest, Some(piece)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rooks the king can castle with, castling is written as the king taking its own rook.
+    /// Every square between the king and rook and their destinations must be empty,
+    /// and the king may not pass through an attacked square.
+    fn castling_rooks(&self) -> u64 {
+        let us = self.side_to_move;
+        let king = self.king_square(us).to_index();
+        let base = king & !7;
+        let mut rooks = 0;
+
+        for side in [KINGSIDE, QUEENSIDE] {
+            let Some(rook) = self.castling[us.to_index()][side] else {
+                continue;
+            };
+            let rook = rook.to_index();
+            let (king_to, rook_to) = if side == KINGSIDE {
+                (base + 6, base + 5)
+            } else {
+                (base + 2, base + 3)
+            };
+
+            let occupied = self.occupied() ^ bit(king) ^ bit(rook);
+            let path =
+                BETWEEN[king][king_to] | bit(king_to) | BETWEEN[rook][rook_to] | bit(rook_to);
+            if path & occupied != 0 {
+                continue;
+            }
+
+            let king_path = BETWEEN[king][king_to] | bit(king_to) | bit(king);
+            if BitBoard(king_path).any(|sq| self.king_attacked(sq.to_index(), occupied)) {
+                continue;
+            }
+
+            rooks |= bit(rook);
+        }
+        rooks
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Board;
+
+    /// Counts with make and unmake, the generator the search uses is checked in `crate::perft`
+    /// <https://www.chessprogramming.org/Perft>
+    fn perft(board: &mut Board, depth: u8) -> u64 {
+        let moves = board.legal_moves();
+        if depth <= 1 {
+            return if depth == 0 { 1 } else { moves.len() as u64 };
+        }
+
+        let mut nodes = 0;
+        for mv in moves {
+            let undo = board.make_move(mv);
+            nodes += perft(board, depth - 1);
+            board.unmake_move(mv, &undo);
+        }
+        nodes
+    }
+
+    #[test]
+    fn test_perft() {
+        // https://www.chessprogramming.org/Perft_Results
+        for (fen, depth, nodes) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                5,
+                4_865_609,
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                4,
+                4_085_603,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674_624),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                4,
+                422_333,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                4,
+                2_103_487,
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                4,
+                3_894_594,
+            ),
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert_eq!(perft(&mut board, depth), nodes, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_perft_960() {
+        // https://www.chessprogramming.org/Chess960_Perft_Results
+        for (fen, nodes) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12_189, 326_672],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18_002, 667_366],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                [20, 479, 10_471, 273_318],
+            ),
+            (
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                [22, 593, 13_440, 382_958],
+            ),
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            for (depth, &expected) in (1..).zip(&nodes) {
+                assert_eq!(perft(&mut board, depth), expected, "{fen} depth {depth}");
+            }
+        }
+    }
+}
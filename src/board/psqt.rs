@@ -0,0 +1,86 @@
+//! Material + PST of every piece on every square, taken from the evaluation parameters
+//!
+//! The board keeps the sum over its pieces up to date while making moves, so the eval does not
+//! have to add it up. Boards are `Copy`, so the table is global instead of a field, and
+//! `set_params` rebuilds it when other parameters are loaded.
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use chess::{Color, Piece};
+use const_for::const_for;
+
+use crate::params::{self, EvalParams, DEFAULT_VALUES, NUM_PARAMS};
+
+/// Middlegame and endgame value of each color, piece and square, see `index`
+static TABLE: [AtomicI32; 2 * 6 * 64 * 2] = {
+    let mut table = [const { AtomicI32::new(0) }; 2 * 6 * 64 * 2];
+    const_for!(i in 0..table.len() => {
+        table[i] = AtomicI32::new(value(&DEFAULT_VALUES, i));
+    });
+    table
+};
+
+#[inline(always)]
+const fn index(color: usize, piece: usize, square: usize) -> usize {
+    ((color * 6 + piece) * 64 + square) * 2
+}
+
+/// Entry `i` of the table from white's point of view, black squares are mirrored
+const fn value(values: &[i32; NUM_PARAMS], i: usize) -> i32 {
+    let (color, piece, square, phase) = (i / 768, i / 128 % 6, i / 2 % 64, i % 2);
+    let square = if color == 0 { square } else { square ^ 56 };
+    let value = if phase == 0 {
+        values[params::MG_VALUE + piece] + values[params::MG_PST[piece] + square]
+    } else {
+        values[params::EG_VALUE + piece] + values[params::EG_PST[piece] + square]
+    };
+
+    if color == 0 {
+        value
+    } else {
+        -value
+    }
+}
+
+/// Material + PST of a piece from white's point of view, (middlegame, endgame)
+#[inline(always)]
+pub fn psqt(piece: Piece, color: Color, square: usize) -> (i32, i32) {
+    let i = index(color.to_index(), piece.to_index(), square);
+    (
+        TABLE[i].load(Ordering::Relaxed),
+        TABLE[i + 1].load(Ordering::Relaxed),
+    )
+}
+
+/// Fills the table from `params`, boards set up before keep the score of the old values
+pub fn set_params(params: &EvalParams) {
+    for (i, entry) in TABLE.iter().enumerate() {
+        entry.store(value(&params.values, i), Ordering::Relaxed);
+    }
+}
+
+mod test {
+    #[test]
+    fn test_value() {
+        use super::*;
+        use chess::Square;
+
+        // Loaded parameters change the entries of both colors, mirrored for black
+        let mut params = EvalParams::default();
+        params[params::MG_VALUE + Piece::Knight.to_index()] = 300;
+        params[params::EG_PST[Piece::Knight.to_index()] + Square::C3.to_index()] = 7;
+        let knight = |color: Color, square: Square| {
+            let i = index(
+                color.to_index(),
+                Piece::Knight.to_index(),
+                square.to_index(),
+            );
+            (value(&params.values, i), value(&params.values, i + 1))
+        };
+
+        let mg = 300 + params[params::MG_PST[Piece::Knight.to_index()] + Square::C3.to_index()];
+        let eg = params[params::EG_VALUE + Piece::Knight.to_index()] + 7;
+        assert_eq!(knight(Color::White, Square::C3), (mg, eg));
+        assert_eq!(knight(Color::Black, Square::C6), (-mg, -eg));
+    }
+}
@@ -0,0 +1,65 @@
+//! Zobrist keys, generated with xorshift64 from a fixed seed so they are stable between builds
+//! <https://www.chessprogramming.org/Zobrist_Hashing>
+
+use const_for::const_for;
+
+pub struct Keys {
+    /// Indexed by color, piece and square
+    pub pieces: [[[u64; 64]; 6]; 2],
+    pub side: u64,
+    /// Indexed by the castling rights as a 4 bit mask
+    pub castling: [u64; 16],
+    /// Indexed by the file of the en passant square
+    pub en_passant: [u64; 8],
+}
+
+pub static KEYS: Keys = {
+    let mut state: u64 = 0x6A09_E667_F3BC_C909;
+    let mut keys = Keys {
+        pieces: [[[0; 64]; 6]; 2],
+        side: 0,
+        castling: [0; 16],
+        en_passant: [0; 8],
+    };
+
+    const_for!(color in 0..2 => {
+        const_for!(piece in 0..6 => {
+            const_for!(square in 0..64 => {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                keys.pieces[color][piece][square] = state;
+            });
+        });
+    });
+
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    keys.side = state;
+
+    // Every combination of rights is the xor of the keys of the single rights
+    let mut single = [0; 4];
+    const_for!(i in 0..4 => {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        single[i] = state;
+    });
+    const_for!(rights in 0..16 => {
+        const_for!(i in 0..4 => {
+            if rights & (1 << i) != 0 {
+                keys.castling[rights] ^= single[i];
+            }
+        });
+    });
+
+    const_for!(file in 0..8 => {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        keys.en_passant[file] = state;
+    });
+
+    keys
+};
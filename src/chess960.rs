@@ -0,0 +1,138 @@
+//! Chess960 support in the UCI front end
+//! <https://www.chessprogramming.org/Chess960>
+//!
+//! The board stores castling as the king taking its own rook, so any king and rook placement
+//! can castle. Positions are read from standard, Shredder-FEN and X-FEN, castling is accepted in
+//! both notations and written as king takes rook when `UCI_Chess960` is set.
+
+use crate::board::Board;
+use crate::utils::History;
+
+/// Sets up the position of a UCI `position` command, pushing the positions to `history`
+/// # Errors
+/// when the command, the FEN or a move is invalid
+pub fn parse_position(line: &str, board: &mut Board, history: &mut History) -> Result<(), String> {
+    let mut tokens = line
+        .split_whitespace()
+        .skip_while(|&x| x != "position")
+        .skip(1);
+
+    match tokens.next() {
+        Some("startpos") => *board = Board::default(),
+        Some("fen") => {
+            let fen: Vec<&str> = tokens.clone().take_while(|&x| x != "moves").collect();
+            *board = Board::from_fen(&fen.join(" "))?;
+            history.push_hist(board.get_hash());
+        }
+        _ => return Err(format!("invalid position command `{line}`")),
+    }
+
+    for mv in tokens.skip_while(|&x| x != "moves").skip(1) {
+        let mv = board
+            .parse_uci(mv)
+            .ok_or_else(|| format!("illegal move `{mv}` on {board}"))?;
+        board.make_move(mv);
+        history.push_hist(board.get_hash());
+    }
+
+    Ok(())
+}
+
+mod test {
+    #[test]
+    fn test_parse_fen() {
+        use super::*;
+        use crate::perft::perft;
+        use chess::{Color, Square};
+
+        // Shredder-FEN and X-FEN of the standard position, Chess960 position 518
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(board, Board::default());
+            let nodes: Vec<u64> = (1..=4).map(|d| perft(&board, d)).collect();
+            assert_eq!(nodes, [20, 400, 8902, 197_281]);
+        }
+
+        // Rights with the rooks off the corners
+        let board =
+            Board::from_fen("1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/R3K1R1 w GAg - 0 1").unwrap();
+        assert_eq!(board.castle_rook(Color::White, true), Some(Square::G1));
+        assert_eq!(board.castle_rook(Color::White, false), Some(Square::A1));
+        assert_eq!(board.castle_rook(Color::Black, true), Some(Square::G8));
+        assert_eq!(board.castle_rook(Color::Black, false), None);
+
+        assert!(Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w X - 0 1").is_err());
+        assert!(Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w H - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_castling_notation() {
+        use super::*;
+        use std::str::FromStr;
+
+        let mut board = Board::default();
+        let mut history = History::new();
+
+        // King takes rook and the standard notation are the same move
+        let line = "position fen r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1 moves e1h1 e8c8";
+        parse_position(line, &mut board, &mut history).unwrap();
+        let expected = Board::from_str("2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2").unwrap();
+        assert_eq!(board, expected);
+
+        let board = Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castle = board.parse_uci("e1g1").unwrap();
+        assert_eq!(board.format_move(castle, true), "e1h1");
+        assert_eq!(board.format_move(castle, false), "e1g1");
+
+        // Castling with the king and rooks off their standard squares
+        let mut board = Board::default();
+        let line = "position fen rk4r1/6p1/8/8/8/8/6P1/RK4R1 w GAga - 0 1 moves b1g1 b8a8";
+        parse_position(line, &mut board, &mut history).unwrap();
+        let expected = Board::from_str("2kr2r1/6p1/8/8/8/8/6P1/R4RK1 w - - 2 2").unwrap();
+        assert_eq!(board, expected);
+
+        assert!(parse_position("position startpos moves e2e5", &mut board, &mut history).is_err());
+    }
+
+    #[test]
+    fn test_perft() {
+        use super::*;
+        use crate::perft::perft;
+
+        // <https://www.chessprogramming.org/Chess960_Perft_Results>, set up through the UCI
+        // front end and counted with the generator the search uses. The first positions of the
+        // list are in the move generator tests.
+        for (fen, nodes) in [
+            (
+                "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                [28, 1120, 31_058, 1_171_749],
+            ),
+            (
+                "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+                [29, 899, 26_578, 824_055],
+            ),
+            (
+                "q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9",
+                [30, 860, 24_566, 732_757],
+            ),
+            (
+                "qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9",
+                [25, 635, 17_054, 465_806],
+            ),
+            (
+                "qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9",
+                [28, 811, 23_175, 679_699],
+            ),
+        ] {
+            let mut board = Board::default();
+            let line = format!("position fen {fen}");
+            parse_position(&line, &mut board, &mut History::new()).unwrap();
+            for (depth, &expected) in (1..).zip(&nodes) {
+                assert_eq!(perft(&board, depth), expected, "{fen} depth {depth}");
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Self-play training data generation
+//! Games are played at a fixed number of nodes from random openings and every quiet position is
+//! written as `FEN | score | result`, with the score and result from white's point of view.
+//! This is the same format `pica tune` reads.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use chess::{BoardStatus, Color, Piece};
+
+use crate::board::Board;
+use crate::engine::Engine;
+use crate::time::TimeManager;
+use crate::utils::{is_mate_score, History};
+
+/// Number of random moves played before the engine takes over
+const RANDOM_PLIES: usize = 8;
+/// Openings that are already this lopsided are thrown away
+const MAX_OPENING_SCORE: i32 = 1000;
+/// Games that go on for longer are called a draw
+const MAX_GAME_PLIES: usize = 400;
+/// Hash size of every worker
+const TT_SIZE_MB: usize = 16;
+
+/// xorshift64
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Bare kings or a single minor piece
+fn is_insufficient_material(board: &Board) -> bool {
+    let pieces = board.combined().popcnt();
+    let minors = (board.pieces(Piece::Knight) | board.pieces(Piece::Bishop)).popcnt();
+
+    pieces == 2 || (pieces == 3 && minors == 1)
+}
+
+/// Plays random moves from the start position, `None` if the game ended on the way
+fn random_opening(rng: &mut Rng) -> Option<Board> {
+    let mut board = Board::default();
+
+    // Also randomize who moves first after the opening
+    for _ in 0..RANDOM_PLIES + (rng.next() % 2) as usize {
+        let moves = board.legal_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        board = board.make_move_new(moves[(rng.next() % moves.len() as u64) as usize]);
+    }
+
+    (board.status() == BoardStatus::Ongoing).then_some(board)
+}
+
+/// Plays one game and returns its quiet positions as `FEN | score | result` records
+fn play_game(rng: &mut Rng, nodes: u64) -> Vec<String> {
+    let time = TimeManager {
+        max_nodes: Some(nodes),
+        ..Default::default()
+    };
+
+    let (mut board, mut eng) = loop {
+        let Some(board) = random_opening(rng) else {
+            continue;
+        };
+        let mut eng = Engine::new(TT_SIZE_MB);
+        let (_, score) = eng.search(board, &time, History::new());
+        if score.abs() < MAX_OPENING_SCORE {
+            break (board, eng);
+        }
+    };
+
+    let mut history = History::new();
+    history.push_hist(board.get_hash());
+    let mut positions = vec![];
+
+    let result = 'game: {
+        for _ in 0..MAX_GAME_PLIES {
+            match board.status() {
+                BoardStatus::Checkmate if board.side_to_move() == Color::White => {
+                    break 'game "0.0"
+                }
+                BoardStatus::Checkmate => break 'game "1.0",
+                BoardStatus::Stalemate => break 'game "0.5",
+                BoardStatus::Ongoing => {}
+            }
+            if history.is_three_rep()
+                || board.halfmove_clock() >= 100
+                || is_insufficient_material(&board)
+            {
+                break 'game "0.5";
+            }
+
+            let (mv, score) = eng.search(board, &time, history);
+            // Only quiet positions with a meaningful score are useful
+            if board.checkers().popcnt() == 0
+                && !board.is_capture(mv)
+                && mv.get_promotion().is_none()
+                && !is_mate_score(score)
+            {
+                let score = match board.side_to_move() {
+                    Color::White => score,
+                    Color::Black => -score,
+                };
+                positions.push(format!("{board} | {score}"));
+            }
+
+            board = board.make_move_new(mv);
+            history.push_hist(board.get_hash());
+        }
+
+        "0.5"
+    };
+
+    positions
+        .into_iter()
+        .map(|x| format!("{x} | {result}"))
+        .collect()
+}
+
+/// Entry point of `pica datagen <output> [games] [threads] [nodes]`
+pub fn run(args: &[String]) {
+    let Some(path) = args.first() else {
+        eprintln!("Usage: pica datagen <output> [games] [threads] [nodes]");
+        return;
+    };
+    let games: usize = args
+        .get(1)
+        .map_or(1000, |x| x.parse().expect("parse games"));
+    let threads: usize = args.get(2).map_or_else(
+        || thread::available_parallelism().map_or(1, std::num::NonZero::get),
+        |x| x.parse().expect("parse threads"),
+    );
+    let nodes: u64 = args
+        .get(3)
+        .map_or(5000, |x| x.parse().expect("parse nodes"));
+
+    let mut out = BufWriter::new(File::create(path).expect("create output file"));
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_nanos() as u64);
+    let next_game = AtomicUsize::new(0);
+    let start = Instant::now();
+
+    thread::scope(|s| {
+        let (sender, receiver) = mpsc::channel();
+
+        for id in 0..threads {
+            let sender = sender.clone();
+            let next_game = &next_game;
+            s.spawn(move || {
+                let mut rng = Rng((seed ^ (id as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)) | 1);
+                while next_game.fetch_add(1, Ordering::Relaxed) < games {
+                    if sender.send(play_game(&mut rng, nodes)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut positions = 0;
+        for (game, records) in receiver.iter().enumerate() {
+            for record in &records {
+                writeln!(out, "{record}").expect("write output file");
+            }
+            positions += records.len();
+
+            if (game + 1) % 10 == 0 || game + 1 == games {
+                println!(
+                    "games {} positions {positions} pos/s {:.0}",
+                    game + 1,
+                    positions as f64 / start.elapsed().as_secs_f64()
+                );
+            }
+        }
+    });
+
+    out.flush().expect("write output file");
+}
+
+mod test {
+    #[test]
+    fn test_play_game() {
+        use super::*;
+        use crate::tune::parse_line;
+
+        let mut rng = Rng(0x1234_5678);
+        let records = play_game(&mut rng, 300);
+        assert!(!records.is_empty());
+
+        // Every record can be read back by the tuner, and all come from the same game
+        let results: Vec<f64> = records
+            .iter()
+            .map(|x| parse_line(x).expect("parse record").1)
+            .collect();
+        assert!(results
+            .iter()
+            .all(|&r| (r - results[0]).abs() < f64::EPSILON));
+    }
+
+    #[test]
+    fn test_node_limit() {
+        use super::*;
+        use crate::stats;
+
+        // The limit covers the whole search, not each iteration
+        let time = TimeManager {
+            max_nodes: Some(1000),
+            ..Default::default()
+        };
+        let (mv, _) = Engine::new(1).search(Board::default(), &time, History::new());
+        assert!(Board::default().legal(mv));
+        assert!(stats::search_nodes() < 2000);
+
+        // Stopping in the first iteration still gives a move
+        let time = TimeManager {
+            max_nodes: Some(1),
+            ..Default::default()
+        };
+        let (mv, _) = Engine::new(1).search(Board::default(), &time, History::new());
+        assert!(Board::default().legal(mv));
+    }
+}
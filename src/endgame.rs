@@ -0,0 +1,435 @@
+//! Knowledge about endgames the evaluation gets wrong, or only finds with a deep search
+//! <https://www.chessprogramming.org/Endgame>
+//!
+//! KPK is looked up in a bitbase built by retrograde analysis the first time it is needed.
+//! A lone king against mating material is driven to the edge, or to the right corner with
+//! bishop and knight, and some drawish material scales the endgame score down.
+
+use chess::{get_king_moves, get_pawn_attacks, BitBoard, Color, Piece, Square, ALL_SQUARES, EMPTY};
+use lazy_static::lazy_static;
+
+use crate::params::{self, EvalParams};
+use crate::position::Position;
+
+/// Bonus for an endgame that is won with correct play, well below the mate scores
+pub const KNOWN_WIN: i32 = 2000;
+/// Scale factor that keeps the endgame score as it is
+pub const SCALE_NORMAL: i32 = 64;
+
+/// Side to move, king squares and a pawn on files a to d and ranks 2 to 7
+const KPK_SIZE: usize = 2 * 64 * 64 * 4 * 6;
+
+// Results while building the bitbase, bit flags so the results of all moves can be merged
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+lazy_static! {
+    /// One bit per KPK position with white having the pawn, set when white wins
+    static ref KPK: Vec<u64> = {
+        let mut db: Vec<u8> = (0..KPK_SIZE).map(kpk_initial).collect();
+
+        // Classify positions from the ones already known until nothing changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..KPK_SIZE {
+                if db[index] == UNKNOWN {
+                    db[index] = kpk_classify(index, &db);
+                    changed |= db[index] != UNKNOWN;
+                }
+            }
+        }
+
+        let mut bits = vec![0; KPK_SIZE / 64];
+        for (index, &result) in db.iter().enumerate() {
+            if result == WIN {
+                bits[index / 64] |= 1 << (index % 64);
+            }
+        }
+        bits
+    };
+}
+
+fn kpk_index(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> usize {
+    usize::from(!white_to_move)
+        | black_king << 1
+        | white_king << 7
+        | (pawn % 8) << 13
+        | (6 - pawn / 8) << 15
+}
+
+/// Side to move, white king, black king and pawn of an index
+fn kpk_decode(index: usize) -> (bool, usize, usize, usize) {
+    let pawn = 8 * (6 - (index >> 15)) + (index >> 13 & 3);
+    (index & 1 == 0, index >> 7 & 63, index >> 1 & 63, pawn)
+}
+
+fn kpk_initial(index: usize) -> u8 {
+    let (white_to_move, white_king, black_king, pawn) = kpk_decode(index);
+    let (wk, bk, p) = (
+        ALL_SQUARES[white_king],
+        ALL_SQUARES[black_king],
+        ALL_SQUARES[pawn],
+    );
+    let pawn_attacks = get_pawn_attacks(p, Color::White, !EMPTY);
+
+    if distance(wk, bk) <= 1
+        || wk == p
+        || bk == p
+        || (white_to_move && pawn_attacks & BitBoard::from_square(bk) != EMPTY)
+    {
+        return INVALID;
+    }
+
+    // The pawn promotes and the queen cannot be taken
+    if white_to_move && pawn / 8 == 6 {
+        let queen = ALL_SQUARES[pawn + 8];
+        if wk != queen && (distance(bk, queen) > 1 || distance(wk, queen) == 1) {
+            return WIN;
+        }
+    }
+
+    // Stalemate or the pawn is lost
+    if !white_to_move {
+        let escapes = get_king_moves(bk) & !(get_king_moves(wk) | pawn_attacks);
+        if escapes == EMPTY
+            || get_king_moves(bk) & !get_king_moves(wk) & BitBoard::from_square(p) != EMPTY
+        {
+            return DRAW;
+        }
+    }
+
+    UNKNOWN
+}
+
+/// White wins if any move wins, black draws if any move draws
+fn kpk_classify(index: usize, db: &[u8]) -> u8 {
+    let (white_to_move, white_king, black_king, pawn) = kpk_decode(index);
+    let mut results = 0;
+
+    if white_to_move {
+        for to in get_king_moves(ALL_SQUARES[white_king]) {
+            results |= db[kpk_index(false, to.to_index(), black_king, pawn)];
+        }
+        // Promotions are already known
+        if pawn / 8 < 6 {
+            results |= db[kpk_index(false, white_king, black_king, pawn + 8)];
+            let blocked = [white_king, black_king].contains(&(pawn + 8));
+            if pawn / 8 == 1 && !blocked {
+                results |= db[kpk_index(false, white_king, black_king, pawn + 16)];
+            }
+        }
+
+        if results & WIN != 0 {
+            WIN
+        } else if results & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            DRAW
+        }
+    } else {
+        for to in get_king_moves(ALL_SQUARES[black_king]) {
+            results |= db[kpk_index(true, white_king, to.to_index(), pawn)];
+        }
+
+        if results & DRAW != 0 {
+            DRAW
+        } else if results & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            WIN
+        }
+    }
+}
+
+/// Whether the side with the pawn wins
+#[must_use]
+pub fn kpk_win(
+    strong: Color,
+    strong_king: Square,
+    pawn: Square,
+    weak_king: Square,
+    strong_to_move: bool,
+) -> bool {
+    // Seen from white with the pawn on the left half of the board
+    let flip = |square: Square| {
+        let index = square.to_index() ^ if strong == Color::White { 0 } else { 56 };
+        if pawn.get_file().to_index() > 3 {
+            index ^ 7
+        } else {
+            index
+        }
+    };
+
+    let index = kpk_index(
+        strong_to_move,
+        flip(strong_king),
+        flip(weak_king),
+        flip(pawn),
+    );
+    KPK[index / 64] >> (index % 64) & 1 != 0
+}
+
+/// Score from white's point of view of endgames with a special evaluation
+#[must_use]
+pub fn evaluate<B: Position>(board: &B, params: &EvalParams) -> Option<i32> {
+    for strong in [Color::White, Color::Black] {
+        let weak = !strong;
+        if board.color_combined(weak).popcnt() != 1 {
+            continue;
+        }
+
+        let ours = *board.color_combined(strong);
+        let count = |piece| (board.pieces(piece) & ours).popcnt();
+        let (pawns, knights, bishops, rooks, queens) = (
+            count(Piece::Pawn),
+            count(Piece::Knight),
+            count(Piece::Bishop),
+            count(Piece::Rook),
+            count(Piece::Queen),
+        );
+        let bishop_pair = {
+            let mut colors = (board.pieces(Piece::Bishop) & ours)
+                .into_iter()
+                .map(is_light);
+            colors.clone().any(|light| light) && colors.any(|light| !light)
+        };
+        let strong_king = board.king_square(strong);
+        let weak_king = board.king_square(weak);
+        let material: i32 = chess::ALL_PIECES[..5]
+            .iter()
+            .map(|&piece| params[params::EG_VALUE + piece.to_index()] * count(piece) as i32)
+            .sum();
+
+        let score = if ours.popcnt() == 2 && pawns == 1 {
+            let pawn = (board.pieces(Piece::Pawn) & ours).to_square();
+            if !kpk_win(
+                strong,
+                strong_king,
+                pawn,
+                weak_king,
+                board.side_to_move() == strong,
+            ) {
+                return Some(0);
+            }
+            let rank = pawn.get_rank().to_index() as i32;
+            let rank = if strong == Color::White {
+                rank
+            } else {
+                7 - rank
+            };
+            KNOWN_WIN + material + 10 * rank
+        } else if ours.popcnt() == 3 && knights == 1 && bishops == 1 {
+            let bishop = (board.pieces(Piece::Bishop) & ours).to_square();
+            KNOWN_WIN
+                + material
+                + push_close(strong_king, weak_king)
+                + push_to_corner(weak_king, bishop)
+        } else if queens > 0 || rooks > 0 || bishop_pair || (bishops > 0 && knights > 0) {
+            // Anything with mating material wins, the rest is technique
+            KNOWN_WIN + material + push_close(strong_king, weak_king) + push_to_edge(weak_king)
+        } else if ours.popcnt() == 1 {
+            // Bare kings
+            0
+        } else {
+            continue;
+        };
+
+        return Some(match strong {
+            Color::White => score,
+            Color::Black => -score,
+        });
+    }
+
+    None
+}
+
+/// How much of the endgame score to keep, out of [`SCALE_NORMAL`]
+#[must_use]
+pub fn scale<B: Position>(board: &B) -> i32 {
+    let bishops = *board.pieces(Piece::Bishop);
+    let pieces = bishops
+        | board.pieces(Piece::Knight)
+        | board.pieces(Piece::Rook)
+        | board.pieces(Piece::Queen);
+
+    for strong in [Color::White, Color::Black] {
+        let ours = *board.color_combined(strong);
+        let pawns = board.pieces(Piece::Pawn) & ours;
+        let weak_king = board.king_square(!strong);
+
+        // A rook pawn with a bishop that does not control the promotion square is a draw when
+        // the lone king gets to the corner
+        if board.color_combined(!strong).popcnt() == 1
+            && pieces & ours == bishops & ours
+            && (bishops & ours).popcnt() == 1
+            && pawns != EMPTY
+        {
+            for file in [chess::File::A, chess::File::H] {
+                if pawns & chess::get_file(file) != pawns {
+                    continue;
+                }
+                let rank = match strong {
+                    Color::White => chess::Rank::Eighth,
+                    Color::Black => chess::Rank::First,
+                };
+                let promotion = Square::make_square(rank, file);
+                let bishop = (bishops & ours).to_square();
+                if is_light(bishop) != is_light(promotion) && distance(weak_king, promotion) <= 1 {
+                    return 0;
+                }
+            }
+        }
+    }
+
+    // Opposite colored bishops with only pawns besides them
+    if pieces == bishops
+        && (bishops & board.color_combined(Color::White)).popcnt() == 1
+        && (bishops & board.color_combined(Color::Black)).popcnt() == 1
+    {
+        let mut squares = bishops.into_iter();
+        if let (Some(a), Some(b)) = (squares.next(), squares.next()) {
+            if is_light(a) != is_light(b) {
+                return SCALE_NORMAL / 2;
+            }
+        }
+    }
+
+    SCALE_NORMAL
+}
+
+/// Chebyshev distance, the number of king moves between two squares
+fn distance(a: Square, b: Square) -> usize {
+    let files = a.get_file().to_index().abs_diff(b.get_file().to_index());
+    let ranks = a.get_rank().to_index().abs_diff(b.get_rank().to_index());
+    files.max(ranks)
+}
+
+fn is_light(square: Square) -> bool {
+    (square.get_file().to_index() + square.get_rank().to_index()) % 2 == 1
+}
+
+/// Bonus for the kings being close, the attacking king has to help mate
+fn push_close(a: Square, b: Square) -> i32 {
+    140 - 20 * distance(a, b) as i32
+}
+
+/// Bonus for the lone king being away from the center
+fn push_to_edge(square: Square) -> i32 {
+    let file = square.get_file().to_index() as i32;
+    let rank = square.get_rank().to_index() as i32;
+    20 * ((3 - file).max(file - 4) + (3 - rank).max(rank - 4))
+}
+
+/// Bonus for the lone king being near a corner the bishop controls, it can only be mated there
+fn push_to_corner(square: Square, bishop: Square) -> i32 {
+    let corners = if is_light(bishop) {
+        [Square::A8, Square::H1]
+    } else {
+        [Square::A1, Square::H8]
+    };
+    let manhattan = |corner: Square| {
+        square
+            .get_file()
+            .to_index()
+            .abs_diff(corner.get_file().to_index())
+            + square
+                .get_rank()
+                .to_index()
+                .abs_diff(corner.get_rank().to_index())
+    };
+    let nearest = corners.into_iter().map(manhattan).min().unwrap_or(0) as i32;
+
+    push_to_edge(square) + 20 * (14 - nearest)
+}
+
+mod test {
+    #[test]
+    fn test_kpk() {
+        use super::*;
+        use crate::board::Board;
+        use std::str::FromStr;
+
+        for (fen, win) in [
+            // Opposition decides
+            ("8/8/4k3/8/4K3/4P3/8/8 w - - 0 1", false),
+            ("8/8/4k3/8/4K3/4P3/8/8 b - - 0 1", true),
+            // The king in front on the sixth wins either way
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", true),
+            ("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1", false),
+            ("8/8/3k4/8/8/4K3/4P3/8 w - - 0 1", true),
+            // The king can not catch the pawn
+            ("8/8/8/8/8/k7/7P/K7 w - - 0 1", true),
+            ("k7/8/8/8/8/8/7P/K7 b - - 0 1", true),
+            ("7k/8/8/8/8/8/7P/K7 b - - 0 1", false),
+            // Rook pawns are drawn with the king in front
+            ("k7/8/K7/P7/8/8/8/8 w - - 0 1", false),
+            ("7k/8/8/6KP/8/8/8/8 w - - 0 1", false),
+            // Stalemate
+            ("k7/P7/1K6/8/8/8/8/8 b - - 0 1", false),
+            // Mirrored for black
+            ("8/8/4p3/4k3/8/4K3/8/8 w - - 0 1", true),
+            ("8/8/4p3/4k3/8/4K3/8/8 b - - 0 1", false),
+        ] {
+            let board = Board::from_str(fen).unwrap();
+            let params = EvalParams::default();
+            let score = evaluate(&board, &params).unwrap();
+            assert_eq!(score != 0, win, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        use super::*;
+        use crate::board::Board;
+        use std::str::FromStr;
+
+        let params = EvalParams::default();
+        let score = |fen| evaluate(&Board::from_str(fen).unwrap(), &params).unwrap();
+
+        // The lone king belongs on the edge
+        assert!(score("7k/8/5K2/8/8/8/8/R7 w - - 0 1") > score("8/8/8/3k4/8/5K2/8/R7 w - - 0 1"));
+        assert!(score("8/8/8/8/8/8/8/q2K3k w - - 0 1") < -KNOWN_WIN);
+        // In the corner of the bishop's color
+        assert!(
+            score("7k/8/5K1B/8/4N3/8/8/8 w - - 0 1") > score("k7/8/1K1B4/8/4N3/8/8/8 w - - 0 1")
+        );
+        // Not enough to mate
+        assert_eq!(score("7k/8/5K2/8/8/8/8/8 w - - 0 1"), 0);
+        assert_eq!(
+            evaluate(
+                &Board::from_str("7k/8/5K2/8/8/8/8/NN6 w - - 0 1").unwrap(),
+                &params
+            ),
+            None
+        );
+        assert_eq!(
+            evaluate(
+                &Board::from_str("7k/p7/5K2/8/8/8/8/R7 w - - 0 1").unwrap(),
+                &params
+            ),
+            None
+        );
+
+        let scale = |fen| scale(&Board::from_str(fen).unwrap());
+        // Wrong bishop
+        assert_eq!(scale("7k/8/5K1P/8/8/8/8/1B6 w - - 0 1"), 0);
+        assert_eq!(scale("7k/8/5K1P/8/8/8/8/B7 w - - 0 1"), SCALE_NORMAL);
+        assert_eq!(scale("8/8/8/8/8/1b6/p2k4/1K6 w - - 0 1"), 0);
+        // Opposite colored bishops
+        assert_eq!(
+            scale("4k3/5p2/8/3b4/8/2B5/5PP1/4K3 w - - 0 1"),
+            SCALE_NORMAL / 2
+        );
+        assert_eq!(
+            scale("4k3/5p2/8/4b3/8/2B5/5PP1/4K3 w - - 0 1"),
+            SCALE_NORMAL
+        );
+        assert_eq!(
+            scale("r3k3/5p2/8/3b4/8/2B5/5PP1/R3K3 w - - 0 1"),
+            SCALE_NORMAL
+        );
+    }
+}
@@ -1,16 +1,28 @@
+use std::sync::Arc;
 use std::time::Instant;
 
-use chess::{Board, BoardStatus, ChessMove};
+use arrayvec::ArrayVec;
+use chess::{ChessMove, Color};
 
 use crate::{
-    bump,
+    bump, endgame,
     eval::eval,
+    nnue::{Accumulator, Network},
+    params::EvalParams,
+    pawn_table::PawnTable,
+    position::Position,
+    spsa::{HISTORY_ORDERING, IIR_DEPTH, QSEARCH_CHECKS, SINGULAR_DEPTH, SINGULAR_MARGIN},
     stats::{
-        self, add_move_index, CHECK_EXTENSION, NODES_SEARCHED, QNODES_SEARCHED, TT_CHECK, TT_HIT,
+        self, add_move_index, CHECK_EXTENSION, MULTI_CUT, NODES_SEARCHED, QNODES_SEARCHED,
+        SINGULAR_EXTENSION, TT_CHECK, TT_HIT,
     },
+    tablebase::Tablebases,
     time::TimeManager,
     tt::{NodeType, TranspositionEntry, TT},
-    utils::{is_mate_score, log_search_statistics, History, MoveGenOrdered, SearchInfo},
+    utils::{
+        cecp_score, is_mate_score, log_search_statistics, mate_in, piece_index, History,
+        MoveGenOrdered, SearchInfo, MAX_MOVES,
+    },
 };
 
 pub const OO: i32 = 10000;
@@ -19,14 +31,31 @@ pub const MAX_PLY: u8 = 200;
 
 pub struct Engine {
     pub tt: TT,
+    pub pawns: PawnTable,
+    pub params: EvalParams,
+    /// Evaluates with the network when loaded, with `params` otherwise and in endgames with
+    /// their own evaluation
+    pub nnue: Option<Arc<Network>>,
     pub info: bool,
+    /// Writes castling as king takes rook
+    pub chess960: bool,
+    /// Writes thinking output in the CECP format instead of UCI `info` lines
+    pub xboard: bool,
+    /// Exact scores for the endgames they cover
+    pub tablebases: Option<Arc<Tablebases>>,
 }
 
 impl Default for Engine {
     fn default() -> Self {
         Self {
             tt: TT::new_with_size_mb(128),
+            pawns: PawnTable::new(),
+            params: EvalParams::default(),
+            nnue: None,
             info: false,
+            chess960: false,
+            xboard: false,
+            tablebases: None,
         }
     }
 }
@@ -36,38 +65,89 @@ impl Engine {
     pub fn new(tt_size_mb: usize) -> Self {
         Self {
             tt: TT::new_with_size_mb(tt_size_mb),
+            pawns: PawnTable::new(),
+            params: EvalParams::default(),
+            nnue: None,
             info: false,
+            chess960: false,
+            xboard: false,
+            tablebases: None,
         }
     }
 
     /// Start a new search
-    pub fn start(&mut self, board: Board, time: &TimeManager, history: History) -> ChessMove {
-        stats::reset();
+    pub fn start<B: Position>(
+        &mut self,
+        board: B,
+        time: &TimeManager,
+        history: History,
+    ) -> ChessMove {
+        self.search(board, time, history).0
+    }
+
+    /// Searches the position and returns the best move with the score of the last completed
+    /// iteration, from the side to move's point of view
+    pub fn search<B: Position>(
+        &mut self,
+        mut board: B,
+        time: &TimeManager,
+        history: History,
+    ) -> (ChessMove, i32) {
+        stats::new_search();
         let start_of_search_instant = Instant::now();
 
         let mut best_mv = None;
+        let mut score = 0;
+
+        // A solved position needs no search
+        if let Some((mv, outcome)) = self.tablebases.as_ref().and_then(|tb| tb.best_move(&board)) {
+            let score = outcome.score(0);
+            let mv_text = board.format_move(mv, self.chess960);
+            if self.info && self.xboard {
+                println!("1 {} 0 0 {mv_text}", cecp_score(score));
+            } else if self.info {
+                let score = match mate_in(score) {
+                    Some(moves) => format!("mate {moves}"),
+                    None => format!("cp {score}"),
+                };
+                println!("info score {score} depth 1 pv {mv_text}");
+            }
+            return (mv, score);
+        }
 
         let mut sinfo = SearchInfo::default();
+        let acc = self.nnue.as_ref().map(|n| Accumulator::new(n, &board));
+
+        // A search stopped during the first iteration still has to play something
+        let or_first_legal =
+            move |mv: Option<ChessMove>| mv.unwrap_or_else(|| board.legal_moves()[0]);
 
         for depth in 1..MAX_PLY {
-            if !time.can_continue_soft(
-                depth,
-                board,
-                unsafe { NODES_SEARCHED },
-                start_of_search_instant,
-            ) {
+            if !time.can_continue_soft(depth, board, stats::search_nodes(), start_of_search_instant)
+            {
                 break;
             }
 
-            stats::reset();
-            let best_score = self.negamax(&board, -OO, OO, depth, 0, &mut sinfo, history, time);
+            stats::next_iteration();
+            let best_score = self.negamax(
+                &mut board,
+                acc.as_ref(),
+                -OO,
+                OO,
+                depth,
+                0,
+                &mut sinfo,
+                history,
+                time,
+            );
 
             if best_score.abs() > 400000 {
                 // println!("broke hard {}", best_score);
-                return best_mv.expect("unable to find best move");
+                return (or_first_legal(best_mv), score);
             }
 
             best_mv = self.tt.get(board.get_hash()).best_move;
+            score = best_score;
 
             if self.info {
                 log_search_statistics(
@@ -78,24 +158,28 @@ impl Engine {
                     &board,
                     &self.tt,
                     best_mv,
+                    self.chess960,
+                    self.xboard,
                 );
             }
 
             // return early if mate is found
             if is_mate_score(best_score) {
-                return best_mv.unwrap();
+                return (or_first_legal(best_mv), score);
             }
         }
 
-        best_mv.expect("unable to find best move")
+        (or_first_legal(best_mv), score)
     }
 
     /// Starts a recursive negamax loop
     /// <https://www.chessprogramming.org/Negamax>
     /// <https://www.chessprogramming.org/Alpha-Beta>
-    fn negamax(
+    #[allow(clippy::too_many_arguments)]
+    fn negamax<B: Position>(
         &mut self,
-        board: &Board,
+        board: &mut B,
+        acc: Option<&Accumulator>,
         mut alpha: i32, // minimum score that a node must reach in order to change the value of a previous node
         beta: i32,      // Beta is the best-score the opponent
         mut depth: u8,
@@ -114,36 +198,52 @@ impl Engine {
         let in_check = board.checkers().0 > 0;
 
         // QSearch to avoid Horizon effect
-        // TODO Try not allowing qsearch if in check
+        // In check the check extension searches one more ply instead
         if (depth == 0 && !in_check) || ply > MAX_PLY {
-            return self.qsearch(board, alpha, beta, sinfo, ply);
+            return self.qsearch(board, acc, alpha, beta, sinfo, ply, 0);
         }
 
         if history.is_three_rep() {
             return 0;
         }
 
+        // Exact score from the tablebases, the root picks its move in `search`
+        if let Some(outcome) = self
+            .tablebases
+            .as_ref()
+            .filter(|_| ply > 0)
+            .and_then(|tb| tb.probe(board))
+        {
+            return outcome.score(ply);
+        }
+
         // Check TT
         let key = board.get_hash();
         let old_alpha = alpha;
         let entry = self.tt.get(key);
         let mut tt_move = None;
+        let excluded = sinfo.excluded[ply as usize];
         bump!(TT_CHECK);
-        if entry.is_valid(key) && entry.depth >= depth {
-            bump!(TT_HIT);
+        if entry.is_valid(key) {
+            // The move is useful for ordering even if the entry is too shallow for a cutoff
+            tt_move = entry.best_move;
+        }
 
-            // Correct entry scores
-            let entry_value = if is_mate_score(entry.value) {
-                if entry.value > 0 {
-                    OO - (OO - entry.value) - ply as i32
-                } else {
-                    -OO + (entry.value + OO) + ply as i32
-                }
+        // Correct entry scores
+        let entry_value = if is_mate_score(entry.value) {
+            if entry.value > 0 {
+                OO - (OO - entry.value) - ply as i32
             } else {
-                entry.value
-            };
+                -OO + (entry.value + OO) + ply as i32
+            }
+        } else {
+            entry.value
+        };
+
+        // The entry belongs to the full search, not to the one without the excluded move
+        if entry.is_valid(key) && entry.depth >= depth && excluded.is_none() {
+            bump!(TT_HIT);
 
-            tt_move = entry.best_move;
             match entry.node_type {
                 NodeType::Exact => return entry_value,
                 NodeType::LowerBound => {
@@ -160,13 +260,60 @@ impl Engine {
             }
         }
 
-        let mut movegen = MoveGenOrdered::new(board, sinfo, ply, tt_move, false);
-        match movegen.status() {
-            chess::BoardStatus::Ongoing => {}
-            chess::BoardStatus::Checkmate => return -OO + i32::from(ply),
-            chess::BoardStatus::Stalemate => return 0,
+        // Internal iterative reduction
+        // Without a hash move ordering is poor, so search shallower and let the next
+        // iteration come back with a hash move
+        // https://www.chessprogramming.org/Internal_Iterative_Reductions
+        if tt_move.is_none() && i32::from(depth) >= IIR_DEPTH.get() {
+            depth -= 1;
+        }
+
+        // Singular extensions
+        // If every move but the hash move fails low by a margin, the hash move is singular and
+        // gets extended. If even without it the search fails high, cut off (multi-cut).
+        // https://www.chessprogramming.org/Singular_Extensions
+        let mut singular_extension = 0;
+        if let Some(tt_move) = tt_move {
+            if ply > 0
+                && ply < MAX_PLY / 2
+                && excluded.is_none()
+                && i32::from(depth) >= SINGULAR_DEPTH.get()
+                && entry.depth + 3 >= depth
+                && matches!(entry.node_type, NodeType::LowerBound | NodeType::Exact)
+                && !is_mate_score(entry_value)
+            {
+                let singular_beta = entry_value - SINGULAR_MARGIN.get() * i32::from(depth);
+
+                sinfo.excluded[ply as usize] = Some(tt_move);
+                let score = self.negamax(
+                    board,
+                    acc,
+                    singular_beta - 1,
+                    singular_beta,
+                    (depth - 1) / 2,
+                    ply,
+                    sinfo,
+                    history,
+                    time,
+                );
+                sinfo.excluded[ply as usize] = None;
+
+                if score.abs() > 400000 {
+                    return -5000000;
+                }
+
+                if score < singular_beta {
+                    bump!(SINGULAR_EXTENSION);
+                    singular_extension = 1;
+                } else if singular_beta >= beta {
+                    bump!(MULTI_CUT);
+                    return singular_beta;
+                }
+            }
         }
 
+        let mut movegen = MoveGenOrdered::new(board, sinfo, ply, tt_move, false);
+
         // Check extention
         // https://www.chessprogramming.org/Check_Extensions
         // Also avoid flooding the stack by limiting it
@@ -179,24 +326,41 @@ impl Engine {
         let mut best_score = -OO; // Initialize best_score to a very low value
                                   // Best move index to track location of best move, e.g. in 94% of cases the best move is first, etc.
         let mut best_move_index = 0;
+        let mut quiets_tried = ArrayVec::<ChessMove, MAX_MOVES>::new();
+        // Moves searched so far, the excluded move is not one of them
+        let mut searched = 0;
 
-        for mv_index in 0..movegen.len {
-            let mv = movegen.pick_next().unwrap();
-            let capture = board.piece_on(mv.get_dest()).is_some();
+        while let Some(mv) = movegen.pick_next(sinfo) {
+            if excluded == Some(mv) {
+                continue;
+            }
+
+            let mv_index = searched;
+            searched += 1;
+            let capture = board.is_capture(mv);
+            let extension = if tt_move == Some(mv) {
+                singular_extension
+            } else {
+                0
+            };
 
-            let new_board = board.make_move_new(mv);
-            let new_history: History = history.push_hist_new(new_board.get_hash());
+            let new_acc = self.make_move_acc(acc, board, mv);
+            sinfo.stack[ply as usize] = Some((piece_index(board, mv), mv.get_dest()));
+            let undo = board.make_move(mv);
+            let new_history: History = history.push_hist_new(board.get_hash());
 
             let score = -self.negamax(
-                &new_board,
+                board,
+                new_acc.as_ref(),
                 -beta,
                 -alpha,
-                depth - 1,
+                depth - 1 + extension,
                 ply + 1,
                 sinfo,
                 new_history,
                 time,
             );
+            board.unmake_move(mv, &undo);
 
             if score.abs() > 400000 {
                 return -5000000;
@@ -209,31 +373,48 @@ impl Engine {
 
                 if score > alpha {
                     alpha = score;
-                    if !capture {
-                        sinfo.history[mv.get_source().to_index()][mv.get_dest().to_index()] +=
-                            u32::from(depth);
-                    }
                 }
             }
 
             if score >= beta {
-                self.tt.set(TranspositionEntry {
-                    key,
-                    value: score,
-                    depth,
-                    node_type: NodeType::LowerBound,
-                    best_move: Some(mv),
-                });
-
-                add_move_index(mv_index);
+                // A cutoff without the excluded move says nothing about the full search
+                if excluded.is_none() {
+                    self.tt.set(TranspositionEntry {
+                        key,
+                        value: score,
+                        depth,
+                        node_type: NodeType::LowerBound,
+                        best_move: Some(mv),
+                    });
+
+                    add_move_index(mv_index);
 
-                if !capture {
-                    sinfo.killers[1][ply as usize] = sinfo.killers[0][ply as usize];
-                    sinfo.killers[0][ply as usize] = Some(mv);
+                    if !capture {
+                        sinfo.killers[1][ply as usize] = sinfo.killers[0][ply as usize];
+                        sinfo.killers[0][ply as usize] = Some(mv);
+                        // The tables are only read for ordering, so an A/B run measures both
+                        if HISTORY_ORDERING.get() {
+                            sinfo.update_quiets(board, mv, &quiets_tried, ply, depth);
+                        }
+                    }
                 }
 
                 return score;
             }
+
+            if !capture {
+                quiets_tried.push(mv);
+            }
+        }
+
+        // Checkmate or stalemate
+        if movegen.yielded == 0 {
+            return if in_check { -OO + i32::from(ply) } else { 0 };
+        }
+
+        // Only the excluded move was legal, or the search without it is not worth storing
+        if excluded.is_some() {
+            return alpha;
         }
 
         // Add move index to statistics
@@ -259,45 +440,65 @@ impl Engine {
 
     /// Quiescence Search
     /// <https://www.chessprogramming.org/Quiescence_Search>
-    fn qsearch(
-        &self,
-        board: &Board,
+    /// Searches captures and queen promotions, all evasions when in check and quiet checks at
+    /// the first qsearch ply (`qply` 0) when `QSEARCH_CHECKS` is set.
+    #[allow(clippy::too_many_arguments)]
+    fn qsearch<B: Position>(
+        &mut self,
+        board: &mut B,
+        acc: Option<&Accumulator>,
         mut alpha: i32,
         beta: i32,
         sinfo: &SearchInfo,
         ply: u8,
+        qply: u8,
     ) -> i32 {
         bump!(QNODES_SEARCHED);
 
-        let standpat = eval(board);
-
-        // Check if standpat causes a beta cutoff
-        if standpat >= beta {
-            return beta;
+        // Keep the ply inside the search stack
+        if ply >= MAX_PLY {
+            return self.evaluate(board, acc);
         }
 
-        // Check if standpat may become a new alpha
-        if standpat > alpha {
-            alpha = standpat;
-        }
+        // There is no standpat when in check, every evasion has to be searched
+        let in_check = board.checkers().0 > 0;
+        if !in_check {
+            let standpat = self.evaluate(board, acc);
 
-        // TODO Add optional TT probing in qsearch
-        // https://www.talkchess.com/forum/viewtopic.php?t=47373
+            // Check if standpat causes a beta cutoff
+            if standpat >= beta {
+                return beta;
+            }
 
-        let mut movegen = MoveGenOrdered::new(board, sinfo, ply, None, true);
-        match movegen.status() {
-            BoardStatus::Ongoing => {}
-            BoardStatus::Checkmate => return -OO + i32::from(ply),
-            BoardStatus::Stalemate => return 0,
+            // Check if standpat may become a new alpha
+            if standpat > alpha {
+                alpha = standpat;
+            }
         }
 
-        for _ in 0..movegen.len {
-            let mv = movegen.pick_next().unwrap();
-            let capture = board.piece_on(mv.get_dest()).is_some();
-            debug_assert!(capture);
+        // TODO Add optional TT probing in qsearch
+        // https://www.talkchess.com/forum/viewtopic.php?t=47373
 
-            let new_board = board.make_move_new(mv);
-            let score = -self.qsearch(&new_board, -beta, -alpha, sinfo, ply + 1);
+        let mut movegen = if in_check {
+            MoveGenOrdered::new(board, sinfo, ply, None, false)
+        } else if qply == 0 && QSEARCH_CHECKS.get() {
+            MoveGenOrdered::new(board, sinfo, ply, None, true).with_checks()
+        } else {
+            MoveGenOrdered::new(board, sinfo, ply, None, true)
+        };
+        while let Some(mv) = movegen.pick_next(sinfo) {
+            let new_acc = self.make_move_acc(acc, board, mv);
+            let undo = board.make_move(mv);
+            let score = -self.qsearch(
+                board,
+                new_acc.as_ref(),
+                -beta,
+                -alpha,
+                sinfo,
+                ply + 1,
+                qply + 1,
+            );
+            board.unmake_move(mv, &undo);
 
             if score >= beta {
                 return beta;
@@ -307,6 +508,38 @@ impl Engine {
             }
         }
 
+        // Checkmate or stalemate
+        if movegen.no_legal_moves() {
+            return if board.checkers().0 == 0 {
+                0
+            } else {
+                -OO + i32::from(ply)
+            };
+        }
+
         alpha
     }
+
+    /// Static evaluation from the side to move's point of view.
+    /// Endgames with their own evaluation are scored the same with or without the network.
+    fn evaluate<B: Position>(&mut self, board: &B, acc: Option<&Accumulator>) -> i32 {
+        match (&self.nnue, acc) {
+            (Some(network), Some(acc)) => match endgame::evaluate(board, &self.params) {
+                Some(sc) if board.side_to_move() == Color::White => sc,
+                Some(sc) => -sc,
+                None => network.evaluate(acc, board.side_to_move()),
+            },
+            _ => eval(board, &self.params, &mut self.pawns),
+        }
+    }
+
+    /// Updates the accumulator for `mv`, if there is a network
+    fn make_move_acc<B: Position>(
+        &self,
+        acc: Option<&Accumulator>,
+        board: &B,
+        mv: ChessMove,
+    ) -> Option<Accumulator> {
+        Some(acc?.make_move_new(self.nnue.as_ref()?, board, mv))
+    }
 }
@@ -1,92 +1,576 @@
-use chess::{BitBoard, Board, Color, EMPTY};
+use chess::{
+    get_bishop_moves, get_file, get_king_moves, get_knight_moves, get_rank, get_rook_moves,
+    BitBoard, CastleRights, Color, File, Rank, Square, EMPTY,
+};
 
 use chess::Color::{Black, White};
 use chess::Piece;
 
-use crate::tables::{EG, ISOLATED_PAWN_MASKS, MG, PASSED_PAWN_MASKS};
+use crate::endgame;
+use crate::params::{self, phase_of, EvalParams, Phase};
+use crate::pawn_table::{pawn_key, PawnEntry, PawnTable};
+use crate::position::Position;
+use crate::tables::{ISOLATED_PAWN_MASKS, PASSED_PAWN_MASKS};
 
+// Default values of the evaluation parameters, see `params.rs` for how they are used
 const PIECE_PHASE_VALUES: [i32; 6] = [0, 1, 1, 2, 4, 0];
-const PASSED_PAWN_BONUS: [i32; 8] = [0, 0, 10, 30, 45, 70, 120, 200];
-const ISOLATED_PAWN_PENALTY: i32 = -20;
-const TEMPO_BONUS: i32 = 10;
+pub const MG_PASSED_PAWN_BONUS: [i32; 8] = [0, 0, 10, 30, 45, 70, 120, 200];
+pub const EG_PASSED_PAWN_BONUS: [i32; 8] = [0, 0, 20, 60, 90, 140, 240, 400];
+pub const MG_ISOLATED_PAWN_PENALTY: i32 = -20;
+pub const EG_ISOLATED_PAWN_PENALTY: i32 = -10;
+pub const MG_BISHOP_PAIR_BONUS: i32 = 30;
+pub const EG_BISHOP_PAIR_BONUS: i32 = 50;
+pub const MG_ROOK_OPEN_FILE_BONUS: i32 = 25;
+pub const EG_ROOK_OPEN_FILE_BONUS: i32 = 10;
+pub const MG_ROOK_HALF_OPEN_FILE_BONUS: i32 = 12;
+pub const EG_ROOK_HALF_OPEN_FILE_BONUS: i32 = 5;
+pub const MG_QUEEN_OPEN_FILE_BONUS: i32 = 6;
+pub const EG_QUEEN_OPEN_FILE_BONUS: i32 = 4;
+pub const MG_QUEEN_HALF_OPEN_FILE_BONUS: i32 = 3;
+pub const EG_QUEEN_HALF_OPEN_FILE_BONUS: i32 = 3;
+pub const MG_ROOK_ON_SEVENTH_BONUS: i32 = 20;
+pub const EG_ROOK_ON_SEVENTH_BONUS: i32 = 30;
+pub const MG_QUEEN_ON_SEVENTH_BONUS: i32 = 10;
+pub const EG_QUEEN_ON_SEVENTH_BONUS: i32 = 20;
+pub const MG_KNIGHT_OUTPOST_BONUS: i32 = 25;
+pub const EG_KNIGHT_OUTPOST_BONUS: i32 = 15;
+pub const MG_BISHOP_OUTPOST_BONUS: i32 = 12;
+pub const EG_BISHOP_OUTPOST_BONUS: i32 = 6;
+pub const MG_TRAPPED_BISHOP_PENALTY: i32 = -100;
+pub const EG_TRAPPED_BISHOP_PENALTY: i32 = -100;
+pub const MG_TRAPPED_ROOK_PENALTY: i32 = -40;
+pub const EG_TRAPPED_ROOK_PENALTY: i32 = -10;
+pub const MG_DOUBLED_PAWN_PENALTY: i32 = -10;
+pub const EG_DOUBLED_PAWN_PENALTY: i32 = -25;
+pub const MG_BACKWARD_PAWN_PENALTY: i32 = -8;
+pub const EG_BACKWARD_PAWN_PENALTY: i32 = -12;
+pub const MG_CONNECTED_PAWN_BONUS: [i32; 8] = [0, 5, 8, 12, 20, 35, 60, 0];
+pub const EG_CONNECTED_PAWN_BONUS: [i32; 8] = [0, 2, 4, 8, 15, 30, 50, 0];
+/// Indexed by the relative rank of the least advanced pawn in front of the king, 0 is no pawn
+pub const PAWN_SHELTER_BONUS: [i32; 8] = [-15, 12, 8, 0, -4, -8, -10, 0];
+/// Indexed by the relative rank of the enemy pawn closest to the king, 0 is no pawn
+pub const PAWN_STORM_PENALTY: [i32; 8] = [0, -30, -25, -12, -5, 0, 0, 0];
+pub const KING_OPEN_FILE_PENALTY: i32 = -20;
+pub const KING_HALF_OPEN_FILE_PENALTY: i32 = -10;
+/// Attack units per attacked king zone square, indexed by the attacking piece.
+/// Not tunable because it only selects the entry of the king safety table.
+const KING_ATTACK_WEIGHTS: [usize; 6] = [0, 2, 2, 3, 5, 0];
+pub const TEMPO_BONUS: i32 = 10;
+
+/// Terms of the evaluation, used to break the score down in [`eval_trace`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Term {
+    Material,
+    PassedPawns,
+    IsolatedPawns,
+    DoubledPawns,
+    BackwardPawns,
+    ConnectedPawns,
+    KingShelter,
+    KingAttacks,
+    Mobility,
+    BishopPair,
+    OpenFiles,
+    SeventhRank,
+    Outposts,
+    TrappedPieces,
+    KingDistance,
+    Tempo,
+}
+
+pub const NUM_TERMS: usize = 16;
+
+impl Term {
+    pub const ALL: [Self; NUM_TERMS] = [
+        Self::Material,
+        Self::PassedPawns,
+        Self::IsolatedPawns,
+        Self::DoubledPawns,
+        Self::BackwardPawns,
+        Self::ConnectedPawns,
+        Self::KingShelter,
+        Self::KingAttacks,
+        Self::Mobility,
+        Self::BishopPair,
+        Self::OpenFiles,
+        Self::SeventhRank,
+        Self::Outposts,
+        Self::TrappedPieces,
+        Self::KingDistance,
+        Self::Tempo,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Material => "Material + PST",
+            Self::PassedPawns => "Passed pawns",
+            Self::IsolatedPawns => "Isolated pawns",
+            Self::DoubledPawns => "Doubled pawns",
+            Self::BackwardPawns => "Backward pawns",
+            Self::ConnectedPawns => "Connected pawns",
+            Self::KingShelter => "King shelter",
+            Self::KingAttacks => "King attacks",
+            Self::Mobility => "Mobility",
+            Self::BishopPair => "Bishop pair",
+            Self::OpenFiles => "Open files",
+            Self::SeventhRank => "Seventh rank",
+            Self::Outposts => "Outposts",
+            Self::TrappedPieces => "Trapped pieces",
+            Self::KingDistance => "King distance",
+            Self::Tempo => "Tempo",
+        }
+    }
+}
+
+/// Collects evaluation terms from the point of view of `color`.
+/// Tunable weights are passed as indices into the parameter vector so that
+/// the same code can compute scores, traces and tuner coefficients.
+trait Accumulator {
+    /// Adds the midgame and endgame parameters at the given indices
+    fn add(&mut self, term: Term, color: Color, mg: usize, eg: usize);
+    /// Adds the midgame only parameter at the given index
+    fn add_mg(&mut self, term: Term, color: Color, mg: usize);
+    /// Adds a score that is not backed by a parameter
+    fn add_raw(&mut self, term: Term, color: Color, mg: i32, eg: i32);
+}
+
+/// Plain midgame and endgame score from white's point of view, used by [`eval`]
+struct Score<'a> {
+    params: &'a EvalParams,
+    mg: i32,
+    eg: i32,
+}
+
+impl<'a> Score<'a> {
+    fn new(params: &'a EvalParams) -> Self {
+        Self {
+            params,
+            mg: 0,
+            eg: 0,
+        }
+    }
+}
+
+impl Accumulator for Score<'_> {
+    #[inline(always)]
+    fn add(&mut self, term: Term, color: Color, mg: usize, eg: usize) {
+        self.add_raw(term, color, self.params[mg], self.params[eg]);
+    }
+
+    #[inline(always)]
+    fn add_mg(&mut self, term: Term, color: Color, mg: usize) {
+        self.add_raw(term, color, self.params[mg], 0);
+    }
+
+    #[inline(always)]
+    fn add_raw(&mut self, _term: Term, color: Color, mg: i32, eg: i32) {
+        match color {
+            White => {
+                self.mg += mg;
+                self.eg += eg;
+            }
+            Black => {
+                self.mg -= mg;
+                self.eg -= eg;
+            }
+        }
+    }
+}
+
+/// Per term breakdown of the evaluation, returned by [`eval_trace`]
+#[derive(Debug, Clone, Default)]
+pub struct EvalTrace {
+    /// Indexed by term, color and phase (0 is midgame, 1 is endgame).
+    /// Each side's values are from its own point of view.
+    pub terms: [[[i32; 2]; 2]; NUM_TERMS],
+    /// Game phase, 24 is the opening and 0 is a pawn endgame
+    pub phase: i32,
+    /// Share of the endgame score that is kept, out of [`endgame::SCALE_NORMAL`]
+    pub scale: i32,
+    /// Final tapered score from white's point of view, or the score of an endgame with its own
+    /// evaluation, which leaves the terms empty
+    pub score: i32,
+}
+
+impl EvalTrace {
+    /// Midgame and endgame totals of a term from white's point of view
+    #[must_use]
+    pub fn total(&self, term: Term) -> (i32, i32) {
+        let [white, black] = self.terms[term as usize];
+        (white[0] - black[0], white[1] - black[1])
+    }
+}
+
+struct Tracer<'a> {
+    params: &'a EvalParams,
+    trace: EvalTrace,
+}
+
+impl Accumulator for Tracer<'_> {
+    fn add(&mut self, term: Term, color: Color, mg: usize, eg: usize) {
+        self.add_raw(term, color, self.params[mg], self.params[eg]);
+    }
+
+    fn add_mg(&mut self, term: Term, color: Color, mg: usize) {
+        self.add_raw(term, color, self.params[mg], 0);
+    }
+
+    fn add_raw(&mut self, term: Term, color: Color, mg: i32, eg: i32) {
+        let t = &mut self.trace.terms[term as usize][color.to_index()];
+        t[0] += mg;
+        t[1] += eg;
+    }
+}
+
+impl std::fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:>16} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+            "Term", "W MG", "W EG", "B MG", "B EG", "MG", "EG"
+        )?;
+        writeln!(f, "{:-<16}-+-{:-<13}-+-{:-<13}-+-{:-<13}", "", "", "", "")?;
+
+        let mut mg = 0;
+        let mut eg = 0;
+        for term in Term::ALL {
+            let [white, black] = self.terms[term as usize];
+            let (term_mg, term_eg) = self.total(term);
+            mg += term_mg;
+            eg += term_eg;
+
+            writeln!(
+                f,
+                "{:>16} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+                term.name(),
+                white[0],
+                white[1],
+                black[0],
+                black[1],
+                term_mg,
+                term_eg
+            )?;
+        }
+
+        writeln!(f, "{:-<16}-+-{:-<13}-+-{:-<13}-+-{:-<13}", "", "", "", "")?;
+        writeln!(f, "{:>16} | {:>43} {:>6}", "Total", mg, eg)?;
+        writeln!(f, "{:>16} | {:>50}", "Phase", self.phase)?;
+        writeln!(f, "{:>16} | {:>50}", "Endgame scale", self.scale)?;
+        write!(f, "{:>16} | {:>50}", "Tapered (white)", self.score)
+    }
+}
+
+/// The evaluation as a linear function of the parameters, used by the tuner
+#[derive(Debug, Clone, Default)]
+pub struct Coefficients {
+    /// Parameter index and its tapered coefficient, from white's point of view
+    pub weights: Vec<(u16, f32)>,
+    /// Tapered score of everything that is not a parameter, from white's point of view
+    pub constant: f32,
+}
+
+#[derive(Default)]
+struct CoefficientCollector {
+    counts: Vec<(usize, i32)>,
+    mg: i32,
+    eg: i32,
+}
+
+impl CoefficientCollector {
+    fn push(&mut self, color: Color, index: usize) {
+        let sign = match color {
+            White => 1,
+            Black => -1,
+        };
+        self.counts.push((index, sign));
+    }
+}
+
+impl Accumulator for CoefficientCollector {
+    fn add(&mut self, _term: Term, color: Color, mg: usize, eg: usize) {
+        self.push(color, mg);
+        self.push(color, eg);
+    }
+
+    fn add_mg(&mut self, _term: Term, color: Color, mg: usize) {
+        self.push(color, mg);
+    }
+
+    fn add_raw(&mut self, _term: Term, color: Color, mg: i32, eg: i32) {
+        let sign = match color {
+            White => 1,
+            Black => -1,
+        };
+        self.mg += sign * mg;
+        self.eg += sign * eg;
+    }
+}
 
 /// Evaluation function.
 /// # Panics
 /// on debug asserts becasue I mucked up the code
 #[inline(never)] // for profiling
 #[must_use]
-pub fn eval(board: &Board) -> i32 {
-    let who2move = match board.side_to_move() {
-        White => 1,
-        Black => -1,
+pub fn eval<B: Position>(board: &B, params: &EvalParams, pawns: &mut PawnTable) -> i32 {
+    // Endgames with their own evaluation
+    if let Some(sc) = endgame::evaluate(board, params) {
+        return match board.side_to_move() {
+            White => sc,
+            Black => -sc,
+        };
+    }
+
+    // Pawn structure, cached in the pawn table
+    let pawn_key = pawn_key(board);
+    let pawn_entry = pawns.get(pawn_key).unwrap_or_else(|| {
+        let entry = eval_pawns(board, params);
+        pawns.set(pawn_key, entry);
+        entry
+    });
+
+    let mut score = Score {
+        params,
+        mg: pawn_entry.mg,
+        eg: pawn_entry.eg,
     };
 
-    let mut mg_sc: i32 = 0; // Midgame score
-    let mut eg_sc: i32 = 0; // Endgame score
+    // Pawn shelter only matters while there are pieces to attack the king
+    for color in [White, Black] {
+        let king_file = board.king_square(color).get_file().to_index();
+        let shelter = pawn_entry.shelter[color.to_index()][king_file];
+        score.add_raw(Term::KingShelter, color, i32::from(shelter), 0);
+    }
 
-    debug_assert!(board.is_sane());
+    // Kept up to date by the board from the loaded parameters
+    match board.psqt() {
+        Some((mg, eg)) => {
+            debug_assert_eq!((mg, eg), {
+                let mut material = Score::new(params);
+                eval_material(board, &mut material);
+                (material.mg, material.eg)
+            });
+            score.add_raw(Term::Material, White, mg, eg);
+        }
+        None => eval_material(board, &mut score),
+    }
+    evaluate(board, &mut score);
+
+    let eg = score.eg * endgame::scale(board) / endgame::SCALE_NORMAL;
+    let sc = taper(score.mg, eg, game_phase(board));
+
+    match board.side_to_move() {
+        White => sc,
+        Black => -sc,
+    }
+}
+
+/// Evaluates the board and breaks the score down per term, side and phase.
+/// Does not use the pawn table.
+#[must_use]
+pub fn eval_trace<B: Position>(board: &B, params: &EvalParams) -> EvalTrace {
+    if let Some(score) = endgame::evaluate(board, params) {
+        return EvalTrace {
+            phase: game_phase(board),
+            scale: endgame::SCALE_NORMAL,
+            score,
+            ..Default::default()
+        };
+    }
+
+    let mut tracer = Tracer {
+        params,
+        trace: EvalTrace::default(),
+    };
+
+    eval_pawn_structure(board, &mut tracer);
+    eval_king_shelters(board, &mut tracer);
+    eval_material(board, &mut tracer);
+    evaluate(board, &mut tracer);
+
+    let mut trace = tracer.trace;
+    let (mg, eg) = Term::ALL
+        .iter()
+        .map(|&term| trace.total(term))
+        .fold((0, 0), |(mg, eg), (a, b)| (mg + a, eg + b));
+    trace.phase = game_phase(board);
+    trace.scale = endgame::scale(board);
+    trace.score = taper(mg, eg * trace.scale / endgame::SCALE_NORMAL, trace.phase);
+
+    trace
+}
+
+/// Expresses the evaluation of the board as a linear function of the parameters.
+/// The tapered score is `constant + sum(weight * param)` up to integer rounding.
+#[must_use]
+pub fn eval_coefficients<B: Position>(board: &B) -> Coefficients {
+    // Endgames with their own evaluation only use the endgame piece values of the strong side,
+    // the rest of their score is what is left with all parameters at zero
+    let zero = EvalParams {
+        values: [0; params::NUM_PARAMS],
+    };
+    if let Some(score) = endgame::evaluate(board, &zero) {
+        let mut weights = vec![];
+        if score != 0 {
+            let strong = if score > 0 { White } else { Black };
+            for piece in chess::ALL_PIECES[..5].iter() {
+                let count = (board.pieces(*piece) & board.color_combined(strong)).popcnt();
+                if count > 0 {
+                    let index = params::EG_VALUE + piece.to_index();
+                    weights.push((index as u16, score.signum() as f32 * count as f32));
+                }
+            }
+        }
+        return Coefficients {
+            weights,
+            constant: score as f32,
+        };
+    }
+
+    let mut collector = CoefficientCollector::default();
+
+    eval_pawn_structure(board, &mut collector);
+    eval_king_shelters(board, &mut collector);
+    eval_material(board, &mut collector);
+    evaluate(board, &mut collector);
+
+    let phase = game_phase(board) as f32;
+    let mg_weight = phase / 24.;
+    let scale = endgame::scale(board) as f32 / endgame::SCALE_NORMAL as f32;
+    let eg_weight = (24. - phase) / 24. * scale;
+
+    // Merge repeated parameters
+    let mut counts = collector.counts;
+    counts.sort_unstable_by_key(|&(index, _)| index);
+    let mut weights: Vec<(u16, f32)> = Vec::with_capacity(counts.len());
+    for (index, count) in counts {
+        let weight = count as f32
+            * match phase_of(index) {
+                Phase::Mg => mg_weight,
+                Phase::Eg => eg_weight,
+            };
+
+        match weights.last_mut() {
+            Some(last) if usize::from(last.0) == index => last.1 += weight,
+            _ => weights.push((index as u16, weight)),
+        }
+    }
+    weights.retain(|&(_, weight)| weight != 0.);
+
+    Coefficients {
+        weights,
+        constant: collector.mg as f32 * mg_weight + collector.eg as f32 * eg_weight,
+    }
+}
 
-    // Calculate the game phase
-    let mut game_phase = 0;
+/// Game phase, 24 is the opening and 0 is a pawn endgame
+fn game_phase<B: Position>(board: &B) -> i32 {
+    let phase: i32 = chess::ALL_PIECES
+        .iter()
+        .map(|&piece| board.pieces(piece).popcnt() as i32 * PIECE_PHASE_VALUES[piece.to_index()])
+        .sum();
 
+    phase.min(24)
+}
+
+/// Tapered score
+fn taper(mg_sc: i32, eg_sc: i32, game_phase: i32) -> i32 {
+    let mg_weight = game_phase;
+    let eg_weight = 24 - game_phase;
+
+    (mg_sc * mg_weight + eg_sc * eg_weight) / 24
+}
+
+/// Material and PST of all pieces, the native board also keeps this up to date incrementally
+#[inline(always)]
+fn eval_material<B: Position, A: Accumulator>(board: &B, acc: &mut A) {
     // Get Pesto values
     for square in *board.combined() {
         if let Some(piece) = board.piece_on(square) {
             let color = unsafe { board.color_on(square).unwrap_unchecked() };
+            let sq_i = match color {
+                White => square.to_index(),
+                Black => square.to_index() ^ 56,
+            };
+            let piece_i = piece.to_index();
 
-            game_phase += PIECE_PHASE_VALUES[piece.to_index()];
-            let sq_i = square.to_index();
-
-            if color == Color::White {
-                mg_sc += MG[piece.to_index()][sq_i];
-                eg_sc += EG[piece.to_index()][sq_i];
-            } else {
-                mg_sc -= MG[piece.to_index()][sq_i ^ 56];
-                eg_sc -= EG[piece.to_index()][sq_i ^ 56];
-            }
+            acc.add(
+                Term::Material,
+                color,
+                params::MG_VALUE + piece_i,
+                params::EG_VALUE + piece_i,
+            );
+            acc.add(
+                Term::Material,
+                color,
+                params::MG_PST[piece_i] + sq_i,
+                params::EG_PST[piece_i] + sq_i,
+            );
         }
     }
+}
 
-    // TODO cache table
-    for square in *board.pieces(Piece::Pawn) {
-        let color = board.color_on(square).unwrap();
-        let color_mul = i32::from(color == White) * 2 - 1;
-        let file = square.get_file().to_index();
-        let rank = square.get_rank().to_index();
+/// Everything but material, the pawn structure and king shelter, which are evaluated or cached
+/// separately
+#[inline(always)]
+fn evaluate<B: Position, A: Accumulator>(board: &B, acc: &mut A) {
+    // Mobility and attacks on the enemy king zone
+    // https://www.chessprogramming.org/Mobility
+    let blockers = *board.combined();
+    for color in [White, Black] {
+        let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+        let enemy_king = board.king_square(!color);
+        let king_zone = get_king_moves(enemy_king) | BitBoard::from_square(enemy_king);
+        let mut king_attackers = 0;
+        let mut king_attack_units = 0;
 
-        let passed_mask = match color {
-            White => PASSED_PAWN_MASKS[0][square.to_index()],
-            Black => PASSED_PAWN_MASKS[1][square.to_index()],
-        };
+        // Squares attacked by enemy pawns or occupied by our own pieces do not count
+        let mobility_area = !(pawn_attacks(enemy_pawns, !color) | board.color_combined(color));
 
-        let front_mask = match color {
-            White => chess::BitBoard::new(0x0101_0101_0101_0101 << square.to_index()),
-            Black => chess::BitBoard::new(0x0101_0101_0101_0101 >> (63 - square.to_index())),
-        } ^ BitBoard::from_square(square);
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for square in board.pieces(piece) & board.color_combined(color) {
+                let attacks = match piece {
+                    Piece::Knight => get_knight_moves(square),
+                    Piece::Bishop => get_bishop_moves(square, blockers),
+                    Piece::Rook => get_rook_moves(square, blockers),
+                    _ => get_bishop_moves(square, blockers) | get_rook_moves(square, blockers),
+                };
+                let count = (attacks & mobility_area).popcnt() as usize;
 
-        if (board.color_combined(!color) & passed_mask == EMPTY)
-            && (board.color_combined(color) & front_mask == EMPTY)
-        {
-            let bonus = if color == White {
-                PASSED_PAWN_BONUS[rank]
-            } else {
-                PASSED_PAWN_BONUS[7 - rank]
-            };
-            mg_sc += color_mul * bonus;
-            eg_sc += color_mul * bonus * 2;
+                acc.add(
+                    Term::Mobility,
+                    color,
+                    params::MG_MOBILITY[piece.to_index()] + count,
+                    params::EG_MOBILITY[piece.to_index()] + count,
+                );
+
+                let zone_attacks = (attacks & king_zone).popcnt() as usize;
+                if zone_attacks > 0 {
+                    king_attackers += 1;
+                    king_attack_units += KING_ATTACK_WEIGHTS[piece.to_index()] * zone_attacks;
+                }
+            }
         }
 
-        // Isolated pawn evaluation
-        let isolated_mask = ISOLATED_PAWN_MASKS[file];
-        let friendly_pawns = board.pieces(Piece::Pawn) & board.color_combined(color);
-        if friendly_pawns & isolated_mask == EMPTY {
-            mg_sc += color_mul * ISOLATED_PAWN_PENALTY;
-            eg_sc += color_mul * ISOLATED_PAWN_PENALTY / 2; // Less penalty in endgame
+        // A lone attacker is rarely dangerous
+        if king_attackers >= 2 {
+            acc.add_mg(
+                Term::KingAttacks,
+                color,
+                params::KING_SAFETY_TABLE + king_attack_units.min(99),
+            );
         }
     }
 
+    // Piece specific terms
+    eval_pieces(board, acc);
+
+    // Passed pawns look at every piece, so they can not be cached with the pawn structure
+    eval_passed_pawns(board, acc);
+
     // King distance
     // Critical for CI/CD because they do not find good endgame moves with those weak cpus
+    // Not a per side term, so it is all put on white's side
     let white_king_sq = board.king_square(White);
     let black_king_sq = board.king_square(Black);
     let a = white_king_sq
@@ -98,59 +582,649 @@ pub fn eval(board: &Board) -> i32 {
         .to_index()
         .abs_diff(black_king_sq.get_rank().to_index()) as i32;
     let d = a * a + b * b;
-    eg_sc -= d;
-    mg_sc += d;
+    acc.add_raw(Term::KingDistance, White, d, -d);
 
     // Tempo bonus I guess
     // From https://www.chessprogramming.org/Tempo:
     // > That bonus is useful mainly in the opening and middle game positions, but can be counterproductive in the endgame.
-    mg_sc += TEMPO_BONUS * who2move;
+    acc.add_mg(Term::Tempo, board.side_to_move(), params::TEMPO_BONUS);
+}
 
-    // Tapered score
-    let game_phase = game_phase.min(24);
-    let mg_weight = game_phase;
-    let eg_weight = 24 - game_phase;
-    let sc = (mg_sc * mg_weight + eg_sc * eg_weight) / 24;
+/// Evaluates bishop pairs, rooks and queens on open files and the seventh rank,
+/// outposts and trapped pieces.
+#[inline(always)]
+fn eval_pieces<B: Position, A: Accumulator>(board: &B, acc: &mut A) {
+    for color in [White, Black] {
+        let ours = *board.color_combined(color);
+        let friendly_pawns = board.pieces(Piece::Pawn) & ours;
+        let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+        let pawn_defended = pawn_attacks(friendly_pawns, color);
+        // Squares are mirrored for black so that the same relative squares can be used
+        let relative = |square: Square| match color {
+            White => square,
+            Black => Square::make_square(
+                Rank::from_index(7 - square.get_rank().to_index()),
+                square.get_file(),
+            ),
+        };
+
+        // Bishop pair
+        if (board.pieces(Piece::Bishop) & ours).popcnt() >= 2 {
+            acc.add(
+                Term::BishopPair,
+                color,
+                params::MG_BISHOP_PAIR_BONUS,
+                params::EG_BISHOP_PAIR_BONUS,
+            );
+        }
+
+        // Rooks and queens on open and half-open files
+        for piece in [Piece::Rook, Piece::Queen] {
+            let (open, half_open) = match piece {
+                Piece::Rook => (
+                    (
+                        params::MG_ROOK_OPEN_FILE_BONUS,
+                        params::EG_ROOK_OPEN_FILE_BONUS,
+                    ),
+                    (
+                        params::MG_ROOK_HALF_OPEN_FILE_BONUS,
+                        params::EG_ROOK_HALF_OPEN_FILE_BONUS,
+                    ),
+                ),
+                _ => (
+                    (
+                        params::MG_QUEEN_OPEN_FILE_BONUS,
+                        params::EG_QUEEN_OPEN_FILE_BONUS,
+                    ),
+                    (
+                        params::MG_QUEEN_HALF_OPEN_FILE_BONUS,
+                        params::EG_QUEEN_HALF_OPEN_FILE_BONUS,
+                    ),
+                ),
+            };
+            let seventh = match piece {
+                Piece::Rook => (
+                    params::MG_ROOK_ON_SEVENTH_BONUS,
+                    params::EG_ROOK_ON_SEVENTH_BONUS,
+                ),
+                _ => (
+                    params::MG_QUEEN_ON_SEVENTH_BONUS,
+                    params::EG_QUEEN_ON_SEVENTH_BONUS,
+                ),
+            };
 
-    sc * who2move
+            for square in board.pieces(piece) & ours {
+                let file_mask = get_file(square.get_file());
+
+                if friendly_pawns & file_mask == EMPTY {
+                    let (mg, eg) = if enemy_pawns & file_mask == EMPTY {
+                        open
+                    } else {
+                        half_open
+                    };
+                    acc.add(Term::OpenFiles, color, mg, eg);
+                }
+
+                // Seventh rank only counts if it traps the king or attacks pawns
+                if relative_rank(square, color) == 6 {
+                    let seventh_rank = get_rank(square.get_rank());
+                    let eighth_rank = get_rank(relative(Square::A8).get_rank());
+
+                    if enemy_pawns & seventh_rank != EMPTY
+                        || BitBoard::from_square(board.king_square(!color)) & eighth_rank != EMPTY
+                    {
+                        acc.add(Term::SeventhRank, color, seventh.0, seventh.1);
+                    }
+                }
+            }
+        }
+
+        // Outposts, minor pieces defended by a pawn that no enemy pawn can chase away
+        // https://www.chessprogramming.org/Outposts
+        for piece in [Piece::Knight, Piece::Bishop] {
+            let (mg, eg) = match piece {
+                Piece::Knight => (
+                    params::MG_KNIGHT_OUTPOST_BONUS,
+                    params::EG_KNIGHT_OUTPOST_BONUS,
+                ),
+                _ => (
+                    params::MG_BISHOP_OUTPOST_BONUS,
+                    params::EG_BISHOP_OUTPOST_BONUS,
+                ),
+            };
+
+            for square in board.pieces(piece) & ours {
+                let rank = relative_rank(square, color);
+                let chasers = PASSED_PAWN_MASKS[color.to_index()][square.to_index()]
+                    & ISOLATED_PAWN_MASKS[square.get_file().to_index()];
+
+                if (3..=5).contains(&rank)
+                    && BitBoard::from_square(square) & pawn_defended != EMPTY
+                    && enemy_pawns & chasers == EMPTY
+                {
+                    acc.add(Term::Outposts, color, mg, eg);
+                }
+            }
+        }
+
+        // Bishop trapped on a7/h7 by a pawn on b6/g6
+        let bishops = board.pieces(Piece::Bishop) & ours;
+        for (bishop, pawn) in [(Square::A7, Square::B6), (Square::H7, Square::G6)] {
+            if bishops & BitBoard::from_square(relative(bishop)) != EMPTY
+                && enemy_pawns & BitBoard::from_square(relative(pawn)) != EMPTY
+            {
+                acc.add(
+                    Term::TrappedPieces,
+                    color,
+                    params::MG_TRAPPED_BISHOP_PENALTY,
+                    params::EG_TRAPPED_BISHOP_PENALTY,
+                );
+            }
+        }
+
+        // Rook trapped in the corner by a king that can no longer castle
+        if board.castle_rights(color) == CastleRights::NoRights {
+            let rooks = board.pieces(Piece::Rook) & ours;
+            let king = board.king_square(color);
+            let kingside = [Square::G1, Square::H1, Square::H2];
+            let queenside = [Square::A1, Square::B1, Square::A2];
+
+            let trapped = if [Square::F1, Square::G1].map(relative).contains(&king) {
+                kingside
+                    .iter()
+                    .any(|&sq| rooks & BitBoard::from_square(relative(sq)) != EMPTY)
+            } else if [Square::B1, Square::C1].map(relative).contains(&king) {
+                queenside
+                    .iter()
+                    .any(|&sq| rooks & BitBoard::from_square(relative(sq)) != EMPTY)
+            } else {
+                false
+            };
+
+            if trapped {
+                acc.add(
+                    Term::TrappedPieces,
+                    color,
+                    params::MG_TRAPPED_ROOK_PENALTY,
+                    params::EG_TRAPPED_ROOK_PENALTY,
+                );
+            }
+        }
+    }
+}
+
+/// Adds pawns with no enemy piece in front of them or on the adjacent files
+/// and no friendly piece in front of them
+#[inline(always)]
+fn eval_passed_pawns<B: Position, A: Accumulator>(board: &B, acc: &mut A) {
+    for color in [White, Black] {
+        for square in board.pieces(Piece::Pawn) & board.color_combined(color) {
+            let passed_mask = PASSED_PAWN_MASKS[color.to_index()][square.to_index()];
+            let front_mask = passed_mask & get_file(square.get_file());
+
+            if board.color_combined(!color) & passed_mask == EMPTY
+                && board.color_combined(color) & front_mask == EMPTY
+            {
+                let rank = relative_rank(square, color);
+                acc.add(
+                    Term::PassedPawns,
+                    color,
+                    params::MG_PASSED_PAWN_BONUS + rank,
+                    params::EG_PASSED_PAWN_BONUS + rank,
+                );
+            }
+        }
+    }
+}
+
+/// Squares attacked by `pawns` of the given color
+#[must_use]
+pub fn pawn_attacks(pawns: BitBoard, color: Color) -> BitBoard {
+    let not_a_file = !get_file(File::A).0;
+    let not_h_file = !get_file(File::H).0;
+
+    BitBoard::new(match color {
+        White => ((pawns.0 & not_a_file) << 7) | ((pawns.0 & not_h_file) << 9),
+        Black => ((pawns.0 & not_a_file) >> 9) | ((pawns.0 & not_h_file) >> 7),
+    })
+}
+
+/// Rank of the square as seen from `color`'s side of the board
+fn relative_rank(square: Square, color: Color) -> usize {
+    match color {
+        White => square.get_rank().to_index(),
+        Black => 7 - square.get_rank().to_index(),
+    }
+}
+
+/// Evaluates the pawn structure.
+/// Only looks at pawns so that the result can be cached by the pawn key.
+#[must_use]
+pub fn eval_pawns<B: Position>(board: &B, params: &EvalParams) -> PawnEntry {
+    let mut score = Score::new(params);
+    eval_pawn_structure(board, &mut score);
+
+    // Pawn shield, pawn storm and open files for every possible king file
+    let mut shelter = [[0; 8]; 2];
+    for color in [White, Black] {
+        for (king_file, king_shelter) in shelter[color.to_index()].iter_mut().enumerate() {
+            let mut file_score = Score::new(params);
+            eval_king_shelter(board, color, king_file, &mut file_score);
+
+            // Stored from the king's point of view
+            *king_shelter = match color {
+                White => file_score.mg as i16,
+                Black => -file_score.mg as i16,
+            };
+        }
+    }
+
+    PawnEntry {
+        mg: score.mg,
+        eg: score.eg,
+        shelter,
+    }
+}
+
+/// Adds the pawn structure terms
+#[inline(always)]
+fn eval_pawn_structure<B: Position, A: Accumulator>(board: &B, acc: &mut A) {
+    for color in [White, Black] {
+        let friendly_pawns = board.pieces(Piece::Pawn) & board.color_combined(color);
+        let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+
+        for square in friendly_pawns {
+            let file = square.get_file().to_index();
+            let rank = relative_rank(square, color);
+
+            let passed_mask = PASSED_PAWN_MASKS[color.to_index()][square.to_index()];
+            let front_mask = passed_mask & get_file(File::from_index(file));
+
+            // Doubled pawns, only the rear pawn is penalised
+            let doubled = friendly_pawns & front_mask != EMPTY;
+            if doubled {
+                acc.add(
+                    Term::DoubledPawns,
+                    color,
+                    params::MG_DOUBLED_PAWN_PENALTY,
+                    params::EG_DOUBLED_PAWN_PENALTY,
+                );
+            }
+
+            // Isolated pawns
+            let isolated_mask = ISOLATED_PAWN_MASKS[file];
+            if friendly_pawns & isolated_mask == EMPTY {
+                acc.add(
+                    Term::IsolatedPawns,
+                    color,
+                    params::MG_ISOLATED_PAWN_PENALTY,
+                    params::EG_ISOLATED_PAWN_PENALTY,
+                );
+                continue;
+            }
+
+            // Connected pawns, either defended by a pawn or standing next to one
+            let defenders = chess::get_pawn_attacks(square, !color, friendly_pawns);
+            let phalanx = friendly_pawns & isolated_mask & get_rank(square.get_rank());
+            if defenders != EMPTY || phalanx != EMPTY {
+                acc.add(
+                    Term::ConnectedPawns,
+                    color,
+                    params::MG_CONNECTED_PAWN_BONUS + rank,
+                    params::EG_CONNECTED_PAWN_BONUS + rank,
+                );
+                continue;
+            }
+
+            // Backward pawns, no pawn on the adjacent files can come to defend it
+            // and the square in front is controlled by an enemy pawn
+            let supporters = friendly_pawns
+                & isolated_mask
+                & !PASSED_PAWN_MASKS[color.to_index()][square.to_index()];
+            let stop_square = match color {
+                White => square.up(),
+                Black => square.down(),
+            };
+            if let Some(stop_square) = stop_square {
+                if supporters == EMPTY
+                    && chess::get_pawn_attacks(stop_square, color, enemy_pawns) != EMPTY
+                {
+                    acc.add(
+                        Term::BackwardPawns,
+                        color,
+                        params::MG_BACKWARD_PAWN_PENALTY,
+                        params::EG_BACKWARD_PAWN_PENALTY,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Adds the king shelter of both kings on their current files
+fn eval_king_shelters<B: Position, A: Accumulator>(board: &B, acc: &mut A) {
+    for color in [White, Black] {
+        let king_file = board.king_square(color).get_file().to_index();
+        eval_king_shelter(board, color, king_file, acc);
+    }
+}
+
+/// Adds the pawn shield, pawn storm and open files around a king on `king_file`
+// https://www.chessprogramming.org/King_Safety
+#[inline(always)]
+fn eval_king_shelter<B: Position, A: Accumulator>(
+    board: &B,
+    color: Color,
+    king_file: usize,
+    acc: &mut A,
+) {
+    let friendly_pawns = board.pieces(Piece::Pawn) & board.color_combined(color);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+
+    for file in king_file.max(1) - 1..=(king_file + 1).min(7) {
+        let file_mask = get_file(File::from_index(file));
+
+        // Least advanced friendly pawn, 0 if there is none
+        let shield_rank = (friendly_pawns & file_mask)
+            .map(|sq| relative_rank(sq, color))
+            .min()
+            .unwrap_or(0);
+        acc.add_mg(
+            Term::KingShelter,
+            color,
+            params::PAWN_SHELTER_BONUS + shield_rank,
+        );
+
+        // Enemy pawn closest to our back rank, 0 if there is none
+        let storm_rank = (enemy_pawns & file_mask)
+            .map(|sq| relative_rank(sq, color))
+            .min()
+            .unwrap_or(0);
+        acc.add_mg(
+            Term::KingShelter,
+            color,
+            params::PAWN_STORM_PENALTY + storm_rank,
+        );
+
+        if friendly_pawns & file_mask == EMPTY {
+            let penalty = if enemy_pawns & file_mask == EMPTY {
+                params::KING_OPEN_FILE_PENALTY
+            } else {
+                params::KING_HALF_OPEN_FILE_PENALTY
+            };
+            acc.add_mg(Term::KingShelter, color, penalty);
+        }
+    }
 }
 
 #[test]
 fn sanity_check() {
+    use crate::board::Board;
     use std::str::FromStr;
 
-    assert!(eval(&Board::from_str("1qkq4/2q5/8/8/8/8/5PPP/7K w - - 0 1").unwrap()) < -2000);
-    assert!(eval(&Board::from_str("k7/ppp5/8/8/8/8/5Q2/4QKQ1 w - - 0 1").unwrap()) > 2000);
+    let params = EvalParams::default();
+    let mut pawns = PawnTable::new();
+
+    assert!(
+        eval(
+            &Board::from_str("1qkq4/2q5/8/8/8/8/5PPP/7K w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ) < -2000
+    );
+    assert!(
+        eval(
+            &Board::from_str("k7/ppp5/8/8/8/8/5Q2/4QKQ1 w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ) > 2000
+    );
 
     // Test for passed pawn scores
     println!(
         "{} - {}",
-        eval(&Board::from_str("6k1/8/8/8/8/P7/8/6K1 w - - 0 1").unwrap()),
+        eval(
+            &Board::from_str("6k1/8/8/8/8/P7/8/6K1 w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ),
         198
     );
     println!(
         "{} - {}",
-        eval(&Board::from_str("6k1/8/8/8/P7/8/8/6K1 w - - 0 1").unwrap()),
+        eval(
+            &Board::from_str("6k1/8/8/8/P7/8/8/6K1 w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ),
         176
     );
     println!(
         "{} - {}",
-        eval(&Board::from_str("6k1/8/8/P7/8/8/8/6K1 w - - 0 1").unwrap()),
+        eval(
+            &Board::from_str("6k1/8/8/P7/8/8/8/6K1 w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ),
         187
     );
     println!(
         "{} - {}",
-        eval(&Board::from_str("6k1/8/P7/8/8/8/8/6K1 w - - 0 1").unwrap()),
+        eval(
+            &Board::from_str("6k1/8/P7/8/8/8/8/6K1 w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ),
         228
     );
     println!(
         "{} - {}",
-        eval(&Board::from_str("6k1/P7/8/8/8/8/8/6K1 w - - 0 1").unwrap()),
+        eval(
+            &Board::from_str("6k1/P7/8/8/8/8/8/6K1 w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ),
         337
     );
     println!(
         "{} - {}",
-        eval(&Board::from_str("Q7/6k1/8/8/8/8/8/6K1 w - - 0 1").unwrap()),
+        eval(
+            &Board::from_str("Q7/6k1/8/8/8/8/8/6K1 w - - 0 1").unwrap(),
+            &params,
+            &mut pawns
+        ),
         915
     );
 }
+
+#[test]
+fn pawn_structure() {
+    use crate::board::Board;
+    use std::str::FromStr;
+
+    let params = EvalParams::default();
+    // Cached entries give the same score
+    let mut pawns = PawnTable::new();
+    let board = Board::from_str("4k3/pp3ppp/8/3p4/8/2P1P3/P4PPP/4K3 w - - 0 1").unwrap();
+    assert_eq!(
+        eval(&board, &params, &mut pawns),
+        eval(&board, &params, &mut pawns)
+    );
+
+    // Doubled and isolated pawns are worse than healthy ones
+    let healthy = eval_pawns(
+        &Board::from_str("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1").unwrap(),
+        &params,
+    );
+    let doubled = eval_pawns(
+        &Board::from_str("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1").unwrap(),
+        &params,
+    );
+    assert!(healthy.mg > doubled.mg);
+    assert!(healthy.eg > doubled.eg);
+
+    // Shelter is better with pawns in front of the king
+    let sheltered = eval_pawns(
+        &Board::from_str("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap(),
+        &params,
+    );
+    let open = eval_pawns(
+        &Board::from_str("4k3/8/8/8/8/8/PPP5/6K1 w - - 0 1").unwrap(),
+        &params,
+    );
+    assert!(sheltered.shelter[0][6] > open.shelter[0][6]);
+}
+
+#[test]
+fn king_safety() {
+    use crate::board::Board;
+    use std::str::FromStr;
+
+    let params = EvalParams::default();
+    // Enemy pawns marching towards the king are dangerous
+    let quiet = eval_pawns(
+        &Board::from_str("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap(),
+        &params,
+    );
+    let storm = eval_pawns(
+        &Board::from_str("6k1/5p2/8/8/8/6pp/5PPP/6K1 w - - 0 1").unwrap(),
+        &params,
+    );
+    assert!(quiet.shelter[0][6] > storm.shelter[0][6]);
+
+    // Pieces swarming the king are worse than the same pieces far away
+    let mut pawns = PawnTable::new();
+    let attacked = Board::from_str("r5k1/5ppp/8/8/8/5q1n/5P1P/6K1 w - - 0 1").unwrap();
+    let defended = Board::from_str("rq4k1/n4ppp/8/8/8/8/5P1P/6K1 w - - 0 1").unwrap();
+    assert!(eval(&defended, &params, &mut pawns) > eval(&attacked, &params, &mut pawns));
+}
+
+#[test]
+fn mobility() {
+    use crate::board::Board;
+    use std::str::FromStr;
+
+    let params = EvalParams::default();
+    let mut pawns = PawnTable::new();
+
+    // A knight in the corner is worse than one in the centre
+    let corner = Board::from_str("4k3/8/8/8/8/8/8/N3K3 w - - 0 1").unwrap();
+    let centre = Board::from_str("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+    assert!(eval(&centre, &params, &mut pawns) > eval(&corner, &params, &mut pawns));
+
+    // Squares attacked by enemy pawns do not count
+    assert_eq!(
+        pawn_attacks(BitBoard::from_square(chess::Square::A2), White),
+        BitBoard::from_square(chess::Square::B3)
+    );
+    assert_eq!(
+        pawn_attacks(BitBoard::from_square(chess::Square::E7), Black),
+        BitBoard::from_square(chess::Square::D6) | BitBoard::from_square(chess::Square::F6)
+    );
+}
+
+#[test]
+fn piece_terms() {
+    use crate::board::Board;
+    use std::str::FromStr;
+
+    let params = EvalParams::default();
+    let score = |fen: &str| {
+        let mut score = Score::new(&params);
+        eval_pieces(&Board::from_str(fen).unwrap(), &mut score);
+        (score.mg, score.eg)
+    };
+
+    // Bishop pair
+    assert!(
+        score("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").1 > score("4k3/8/8/8/8/8/8/2N1KB2 w - - 0 1").1
+    );
+
+    // Rook on an open file is better than one behind its own pawn
+    assert!(
+        score("4k3/p7/8/8/8/8/P7/3RK3 w - - 0 1").0 > score("4k3/p7/8/8/8/8/P7/R3K3 w - - 0 1").0
+    );
+
+    // Rook on the seventh
+    assert!(
+        score("4k3/pR6/8/8/8/8/8/4K3 w - - 0 1").1 > score("4k3/p7/1R6/8/8/8/8/4K3 w - - 0 1").1
+    );
+
+    // Knight outpost, defended by a pawn and unreachable by enemy pawns
+    assert!(
+        score("4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1").0
+            > score("4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1").0
+    );
+
+    // Trapped bishop and rook, mirrored for black
+    assert!(score("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1").0 < 0);
+    assert!(score("4k3/8/8/8/8/1P6/b7/4K3 w - - 0 1").0 > 0);
+    assert!(score("4k3/8/8/8/8/8/8/5K1R w - - 0 1").0 < 0);
+}
+
+#[test]
+fn trace() {
+    use crate::board::Board;
+    use std::str::FromStr;
+
+    let params = EvalParams::default();
+    let mut pawns = PawnTable::new();
+
+    // The trace adds up to the same score as the normal evaluation
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w KQ - 0 8",
+        "6k1/5p2/8/8/8/6pp/5PPP/6K1 b - - 0 1",
+        // KPK, KRK and opposite colored bishops, with their own evaluation or scaling
+        "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+        "8/8/8/3k4/8/8/8/R3K3 b - - 0 1",
+        "8/5k2/3b1p2/8/2P5/3B1K2/5P2/8 w - - 0 1",
+    ] {
+        let board = Board::from_str(fen).unwrap();
+        let trace = eval_trace(&board, &params);
+        let sc = match board.side_to_move() {
+            White => trace.score,
+            Black => -trace.score,
+        };
+        assert_eq!(sc, eval(&board, &params, &mut pawns), "{fen}");
+    }
+
+    // Symmetric position
+    let trace = eval_trace(&Board::default(), &params);
+    assert_eq!(trace.phase, 24);
+    assert_eq!(trace.total(Term::Material), (0, 0));
+    assert_eq!(trace.total(Term::Tempo), (TEMPO_BONUS, 0));
+}
+
+#[test]
+fn coefficients() {
+    use crate::board::Board;
+    use std::str::FromStr;
+
+    let params = EvalParams::default();
+
+    // The linear form gives the same score as the evaluation
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w KQ - 0 8",
+        "r5k1/5ppp/8/8/8/5q1n/5P1P/6K1 w - - 0 1",
+        "8/3k4/8/8/3PK3/8/8/8 w - - 0 1",
+        "8/8/8/3k4/8/8/8/R3K3 b - - 0 1",
+        "8/5k2/3b1p2/8/2P5/3B1K2/5P2/8 w - - 0 1",
+    ] {
+        let board = Board::from_str(fen).unwrap();
+        let coefficients = eval_coefficients(&board);
+        let linear = coefficients.constant
+            + coefficients
+                .weights
+                .iter()
+                .map(|&(i, w)| w * params[usize::from(i)] as f32)
+                .sum::<f32>();
+
+        let score = eval_trace(&board, &params).score;
+        assert!(
+            (linear - score as f32).abs() <= 1.,
+            "{fen}: {linear} {score}"
+        );
+    }
+}
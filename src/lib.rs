@@ -1,7 +1,23 @@
+pub mod board;
+pub mod book;
+pub mod chess960;
+pub mod datagen;
+pub mod endgame;
 pub mod engine;
 pub mod eval;
+pub mod makebook;
+pub mod nnue;
+pub mod params;
+pub mod pawn_table;
+pub mod perft;
+pub mod pgn;
+pub mod position;
+pub mod spsa;
+pub mod tablebase;
 pub mod time;
+pub mod tune;
 pub mod utils;
+pub mod xboard;
 
 mod stats;
 mod tables;
@@ -1,35 +1,95 @@
+mod board;
+mod book;
+mod chess960;
+mod datagen;
+mod endgame;
 mod engine;
 mod eval;
+mod makebook;
+mod nnue;
+mod params;
+mod pawn_table;
+mod perft;
+mod pgn;
+mod position;
+mod spsa;
 mod stats;
+mod tablebase;
 mod tables;
 mod tests;
 mod time;
 mod tt;
+mod tune;
 mod utils;
+mod xboard;
 
 use std::env::args;
 use std::io;
 use std::io::BufRead;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use chess::Board;
+use board::{psqt, Board};
+use book::Book;
 use engine::Engine;
+use nnue::Network;
+use params::EvalParams;
+use tablebase::Tablebases;
 use time::TimeManager;
 use utils::History;
 use vampirc_uci::parse_one;
 use vampirc_uci::UciMessage;
 
+/// Network loaded at startup, the hand crafted eval is used if it does not exist
+const DEFAULT_NETWORK: &str = "pica.nnue";
+
 fn main() {
     let mut tt_size_mb = 256;
     let mut info = true;
+    let mut chess960 = false;
+    let mut params = EvalParams::default();
+    let mut nnue = Network::load(DEFAULT_NETWORK).ok().map(Arc::new);
+    let mut book: Option<Book> = None;
+    let mut tablebases: Option<Arc<Tablebases>> = None;
+    let mut own_book = false;
+    let mut book_best = false;
+    // In moves
+    let mut book_depth = 20;
 
     let mut board = Board::default();
     let mut eng = Engine {
         info,
+        nnue: nnue.clone(),
         ..Default::default()
     };
     let mut hist = History::new();
 
+    // `pica tune <positions> [epochs] [output]` runs the Texel tuner
+    let arguments: Vec<String> = args().collect();
+    if arguments.get(1).is_some_and(|x| x == "tune") {
+        tune::run(&arguments[2..]);
+        return;
+    }
+
+    // `pica datagen <output> [games] [threads] [nodes]` generates training data from self-play
+    if arguments.get(1).is_some_and(|x| x == "datagen") {
+        datagen::run(&arguments[2..]);
+        return;
+    }
+
+    // `pica makebook <games.pgn> <book.bin> [plies] [min games] [results]` builds an opening book
+    if arguments.get(1).is_some_and(|x| x == "makebook") {
+        makebook::run(&arguments[2..]);
+        return;
+    }
+
+    // `pica tbgen <dir> <signatures...>` builds endgame tablebases like `KRKP`
+    if arguments.get(1).is_some_and(|x| x == "tbgen") {
+        tablebase::run(&arguments[2..]);
+        return;
+    }
+
     // Check if args contain `--bench` and if so, search do a depth of 9
     if args().any(|x| x.contains("--bench")) {
         eng.start(
@@ -43,12 +103,29 @@ fn main() {
         return;
     }
 
-    for line in io::stdin().lock().lines() {
-        let line = line.expect("receive stdin");
+    let mut lines = io::stdin()
+        .lock()
+        .lines()
+        .map(|x| x.expect("receive stdin"));
+    while let Some(line) = lines.next() {
+        // A CECP GUI starts with `xboard` or `protover`, the rest of the session is handed over
+        if ["xboard", "protover"].contains(&line.split_whitespace().next().unwrap_or_default()) {
+            // Stdin is read on another thread from now on, the buffered input stays with it
+            drop(lines);
+            let lines = || io::stdin().lines().map(|x| x.expect("receive stdin"));
+            xboard::run(&line, lines, || {
+                let mut eng = Engine::new(tt_size_mb);
+                eng.params = params.clone();
+                eng.nnue = nnue.clone();
+                eng.tablebases = tablebases.clone();
+                eng
+            });
+            return;
+        }
 
         // Print move index dist
         if line.trim() == "dist" {
-            let x = unsafe { stats::MOVE_INDEX_DIST };
+            let x = stats::MOVE_INDEX_DIST.with_borrow(|x| *x);
             let sum: u32 = x.iter().sum();
             for (i, x) in x.iter().enumerate() {
                 if *x == 0 && i != 0 {
@@ -59,6 +136,46 @@ fn main() {
             continue;
         }
 
+        // Print the evaluation breakdown of the current position
+        if line.trim() == "eval" {
+            println!("{}", eval::eval_trace(&board, &eng.params));
+            if let Some(network) = &eng.nnue {
+                let acc = nnue::Accumulator::new(network, &board);
+                let score = network.evaluate(&acc, board.side_to_move());
+                println!("NNUE {score} (side to move)");
+            }
+            continue;
+        }
+
+        // Print the SPSA parameters in OpenBench format
+        if line.trim() == "spsa" {
+            print!("{}", spsa::openbench());
+            continue;
+        }
+
+        // Dump the evaluation parameters in the `EvalFile` format
+        if line.trim() == "params" {
+            print!("{}", eng.params);
+            continue;
+        }
+
+        // Parsed here as the UCI parser knows neither Shredder-FEN nor king takes rook castling
+        if line.trim_start().starts_with("position") {
+            if let Err(e) = chess960::parse_position(&line, &mut board, &mut hist) {
+                eprintln!("> {e}");
+            }
+            continue;
+        }
+
+        // `go perft <depth>` prints the node count of every move and the total
+        if let Some(depth) = line.trim().strip_prefix("go perft") {
+            match depth.trim().parse() {
+                Ok(depth) => print!("{}", perft::report(&board, depth, chess960)),
+                Err(_) => eprintln!("> Invalid perft depth `{}`", depth.trim()),
+            }
+            continue;
+        }
+
         let msg: UciMessage = parse_one(&line);
         match msg {
             UciMessage::Uci => {
@@ -68,6 +185,15 @@ fn main() {
                 // List options
                 println!("option name Hash type spin default 256 min 1 max 8192");
                 println!("option name Info type check default true");
+                println!("option name UCI_Chess960 type check default false");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name EvalNetwork type string default {DEFAULT_NETWORK}");
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name BookBestMove type check default false");
+                println!("option name BookDepth type spin default 20 min 0 max 500");
+                println!("option name TablebasePath type string default <empty>");
+                print!("{}", spsa::uci_options());
 
                 println!("uciok");
             }
@@ -78,6 +204,10 @@ fn main() {
                 board = Board::default();
                 eng = Engine::new(tt_size_mb);
                 eng.info = info;
+                eng.chess960 = chess960;
+                eng.params = params.clone();
+                eng.nnue = nnue.clone();
+                eng.tablebases = tablebases.clone();
                 hist = History::new();
             }
             UciMessage::SetOption { name, value } => {
@@ -85,7 +215,45 @@ fn main() {
                     match name.as_str() {
                         "Hash" => tt_size_mb = value.parse().expect("parse"),
                         "Info" => info = value.parse().expect("parse"),
-                        _ => eprintln!("> Invalid name!"),
+                        "UCI_Chess960" => chess960 = value.parse().expect("parse"),
+                        "EvalFile" if value.is_empty() || value == "<empty>" => {
+                            params = EvalParams::default();
+                            psqt::set_params(&params);
+                            board.refresh_psqt();
+                        }
+                        "EvalFile" => match EvalParams::load(&value) {
+                            Ok(x) => {
+                                params = x;
+                                psqt::set_params(&params);
+                                board.refresh_psqt();
+                            }
+                            Err(e) => eprintln!("> Could not load eval file: {e}"),
+                        },
+                        "EvalNetwork" if value.is_empty() || value == "<empty>" => nnue = None,
+                        "EvalNetwork" => match Network::load(&value) {
+                            Ok(x) => nnue = Some(Arc::new(x)),
+                            Err(e) => eprintln!("> Could not load network: {e}"),
+                        },
+                        "OwnBook" => own_book = value.parse().expect("parse"),
+                        "BookFile" if value.is_empty() || value == "<empty>" => book = None,
+                        "BookFile" => match Book::load(&value) {
+                            Ok(x) => book = Some(x),
+                            Err(e) => eprintln!("> Could not load book: {e}"),
+                        },
+                        "BookBestMove" => book_best = value.parse().expect("parse"),
+                        "BookDepth" => book_depth = value.parse().expect("parse"),
+                        "TablebasePath" if value.is_empty() || value == "<empty>" => {
+                            tablebases = None;
+                        }
+                        "TablebasePath" => match Tablebases::load(&value) {
+                            Ok(x) => tablebases = Some(Arc::new(x)),
+                            Err(e) => eprintln!("> Could not load tablebases: {e}"),
+                        },
+                        _ => {
+                            if let Err(e) = spsa::set_option(&name, &value, &mut params) {
+                                eprintln!("> {e}");
+                            }
+                        }
                     }
                 } else {
                     eprintln!("> No value recieved!");
@@ -94,27 +262,12 @@ fn main() {
                 // Reset engine
                 eng = Engine::new(tt_size_mb);
                 eng.info = info;
+                eng.chess960 = chess960;
+                eng.params = params.clone();
+                eng.nnue = nnue.clone();
+                eng.tablebases = tablebases.clone();
                 hist = History::new();
             }
-            UciMessage::Position {
-                startpos,
-                fen,
-                moves,
-            } => {
-                if startpos {
-                    board = Board::default();
-                }
-
-                if let Some(fen) = fen {
-                    board = Board::from_str(fen.as_str()).expect("Parse fen");
-                    hist.push_hist(board.get_hash());
-                }
-
-                for mv in moves {
-                    board = board.make_move_new(mv);
-                    hist.push_hist(board.get_hash());
-                }
-            }
             UciMessage::Go {
                 time_control,
                 search_control: _,
@@ -127,8 +280,25 @@ fn main() {
                     },
                 };
 
-                let mv = eng.start(board, &tc, hist);
-                println!("bestmove {mv}");
+                // A book move is played without searching
+                let random = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |x| x.as_nanos() as u64);
+                let book_move = book
+                    .as_ref()
+                    .filter(|_| own_book && board.fullmove_number() <= book_depth)
+                    .and_then(|book| book.probe(&board, book_best, random));
+
+                let mv = match book_move {
+                    Some(mv) => {
+                        if info {
+                            println!("info string book move");
+                        }
+                        mv
+                    }
+                    None => eng.start(board, &tc, hist),
+                };
+                println!("bestmove {}", board.format_move(mv, chess960));
             }
             UciMessage::Quit => {
                 return;
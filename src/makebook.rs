@@ -0,0 +1,161 @@
+//! Builds a Polyglot book from PGN games
+//! Every move played in the first plies of the selected games is counted, weighted by the
+//! points the side that played it scored: 2 for a win, 1 for a draw or an unfinished game.
+//! Moves only ever played in lost games get no weight and are left out.
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::book::{encode_move, polyglot_key, Book, Entry};
+use crate::pgn::{parse_pgn, parse_san, Game, RESULTS};
+
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    games: u32,
+    /// In half points for the side that played the move
+    points: u32,
+}
+
+/// Book of the moves played in the first `plies` plies of games with one of `results`, that
+/// were played in at least `min_games` of them. Games stop counting at the first move that is
+/// not legal.
+#[must_use]
+pub fn build(games: &[Game], plies: usize, min_games: u32, results: &[&str]) -> Book {
+    let mut stats: HashMap<(u64, u16), Stats> = HashMap::new();
+
+    for game in games {
+        if !results.contains(&game.result.as_str()) {
+            continue;
+        }
+        let Ok(mut board) = game.start() else {
+            continue;
+        };
+
+        for san in game.moves.iter().take(plies) {
+            let Some(mv) = parse_san(&board, san) else {
+                break;
+            };
+            // Half points of white
+            let white = match game.result.as_str() {
+                "1-0" => 2,
+                "0-1" => 0,
+                _ => 1,
+            };
+            let entry = stats
+                .entry((polyglot_key(&board), encode_move(&board, mv)))
+                .or_default();
+            entry.games += 1;
+            entry.points += if board.side_to_move() == chess::Color::White {
+                white
+            } else {
+                2 - white
+            };
+            board = board.make_move_new(mv);
+        }
+    }
+
+    let stats: Vec<_> = stats
+        .into_iter()
+        .filter(|(_, stats)| stats.games >= min_games && stats.points > 0)
+        .collect();
+    // Big collections can score more than a weight holds
+    let max = stats
+        .iter()
+        .map(|(_, stats)| stats.points)
+        .max()
+        .unwrap_or(0);
+    let scale = max.div_ceil(u32::from(u16::MAX)).max(1);
+
+    let mut entries: Vec<Entry> = stats
+        .into_iter()
+        .map(|((key, mv), stats)| Entry {
+            key,
+            mv,
+            weight: u16::try_from(stats.points.div_ceil(scale)).expect("scaled weight"),
+            learn: 0,
+        })
+        .collect();
+    // Best moves first within a position, then by move so the output is deterministic
+    entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight, entry.mv));
+
+    Book::from_entries(entries)
+}
+
+/// `pica makebook <games.pgn> <book.bin> [plies] [min games] [results]`, `results` is a comma
+/// separated list like `1-0,0-1,1/2-1/2`, by default every game counts
+pub fn run(args: &[String]) {
+    let (Some(input), Some(output)) = (args.first(), args.get(1)) else {
+        eprintln!("Usage: pica makebook <games.pgn> <book.bin> [plies] [min games] [results]");
+        return;
+    };
+    let plies: usize = args.get(2).map_or(20, |x| x.parse().expect("parse plies"));
+    let min_games: u32 = args
+        .get(3)
+        .map_or(1, |x| x.parse().expect("parse min games"));
+    let results: Vec<&str> = args
+        .get(4)
+        .map_or(RESULTS.to_vec(), |x| x.split(',').collect());
+    if let Some(result) = results.iter().find(|x| !RESULTS.contains(x)) {
+        eprintln!(
+            "Unknown result {result}, expected one of {}",
+            RESULTS.join(" ")
+        );
+        return;
+    }
+
+    let text = fs::read_to_string(input).expect("read games");
+    let games = parse_pgn(&text);
+    let book = build(&games, plies, min_games, &results);
+    fs::write(output, book.to_bytes()).expect("write book");
+
+    println!(
+        "Read {} games, wrote {} entries to {output}",
+        games.len(),
+        book.entries().len()
+    );
+}
+
+mod test {
+    #[test]
+    fn test_build() {
+        use super::*;
+        use crate::board::Board;
+        use chess::ChessMove;
+        use std::str::FromStr;
+
+        let pgn = r#"
+[Result "1-0"]
+1. e4 e5 2. Nf3 Nc6 1-0
+
+[Result "1/2-1/2"]
+1. e4 c5 2. Nf3 1/2-1/2
+
+[Result "0-1"]
+1. d4 d5 0-1
+
+[FEN "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1"]
+1. O-O O-O-O *
+"#;
+        let games = parse_pgn(pgn);
+        let book = Book::from_bytes(&build(&games, 2, 1, &RESULTS).to_bytes()).unwrap();
+        let mv = |x| ChessMove::from_str(x).unwrap();
+
+        let board = Board::default();
+        // 1. d4 only lost
+        assert_eq!(book.moves(&board), [(mv("e2e4"), 3)]);
+        let board = board.make_move_new(mv("e2e4"));
+        assert_eq!(book.moves(&board), [(mv("c7c5"), 1)]);
+        // Past the ply limit
+        let board = board.make_move_new(mv("e7e5"));
+        assert!(book.moves(&board).is_empty());
+
+        let board = Board::from_str("r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1").unwrap();
+        assert_eq!(book.moves(&board), [(mv("e1h1"), 1)]);
+        let board = board.make_move_new(mv("e1h1"));
+        assert_eq!(book.moves(&board), [(mv("e8a8"), 1)]);
+
+        // Only decisive games played at least twice
+        assert_eq!(build(&games, 2, 2, &["1-0", "0-1"]).entries().len(), 0);
+        assert_eq!(build(&games, 2, 2, &RESULTS).entries().len(), 1);
+    }
+}
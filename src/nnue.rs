@@ -0,0 +1,303 @@
+//! NNUE evaluation
+//! <https://www.chessprogramming.org/NNUE>
+//!
+//! A (768 -> HIDDEN)x2 -> 1 perspective network with a clipped ReLU.
+//! All inner loops work on fixed size `i16` arrays so they auto-vectorize to NEON and SSE/AVX.
+
+use std::fs;
+
+use chess::{ChessMove, Color, File, Piece, Square, ALL_COLORS, ALL_PIECES};
+
+use crate::position::Position;
+
+/// Size of the hidden layer
+pub const HIDDEN: usize = 128;
+/// Number of input features, one per color, piece and square
+const FEATURES: usize = 768;
+
+/// Quantization of the feature layer
+const QA: i32 = 255;
+/// Quantization of the output layer
+const QB: i32 = 64;
+/// Scale from the network output to centipawns
+const SCALE: i32 = 400;
+
+/// Network weights.
+/// The file format is the raw little endian `i16` layout written by bullet:
+/// feature weights `[768][HIDDEN]`, feature biases `[HIDDEN]`,
+/// output weights `[2 * HIDDEN]` and the output bias, optionally padded to 64 bytes.
+pub struct Network {
+    feature_weights: Vec<[i16; HIDDEN]>,
+    feature_bias: [i16; HIDDEN],
+    output_weights: [[i16; HIDDEN]; 2],
+    output_bias: i16,
+}
+
+impl Network {
+    /// Number of bytes of a network file without padding
+    pub const SIZE: usize = 2 * (FEATURES * HIDDEN + HIDDEN + 2 * HIDDEN + 1);
+
+    /// # Errors
+    /// when the size does not match the network architecture
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < Self::SIZE || bytes.len() >= Self::SIZE + 64 {
+            return Err(format!(
+                "expected a network of {} bytes, got {}",
+                Self::SIZE,
+                bytes.len()
+            ));
+        }
+
+        let mut values = bytes
+            .chunks_exact(2)
+            .map(|x| i16::from_le_bytes([x[0], x[1]]));
+        let mut read = |out: &mut [i16]| {
+            for x in out {
+                *x = values.next().unwrap_or_default();
+            }
+        };
+
+        let mut network = Self {
+            feature_weights: vec![[0; HIDDEN]; FEATURES],
+            feature_bias: [0; HIDDEN],
+            output_weights: [[0; HIDDEN]; 2],
+            output_bias: 0,
+        };
+        for weights in &mut network.feature_weights {
+            read(weights);
+        }
+        read(&mut network.feature_bias);
+        read(&mut network.output_weights[0]);
+        read(&mut network.output_weights[1]);
+        read(std::slice::from_mut(&mut network.output_bias));
+
+        Ok(network)
+    }
+
+    /// # Errors
+    /// when the file cannot be read or has the wrong size
+    pub fn load(path: &str) -> Result<Self, String> {
+        Self::from_bytes(&fs::read(path).map_err(|e| format!("{path}: {e}"))?)
+    }
+
+    /// Evaluates the position from the side to move's point of view
+    #[must_use]
+    pub fn evaluate(&self, acc: &Accumulator, side_to_move: Color) -> i32 {
+        let us = &acc.values[side_to_move.to_index()];
+        let them = &acc.values[(!side_to_move).to_index()];
+
+        let output = crelu_dot(us, &self.output_weights[0])
+            + crelu_dot(them, &self.output_weights[1])
+            + i32::from(self.output_bias);
+
+        output * SCALE / (QA * QB)
+    }
+}
+
+/// Sum of `clamp(x, 0, QA) * w`
+#[inline(always)]
+fn crelu_dot(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&x, &w)| i32::from(x).clamp(0, QA) * i32::from(w))
+        .sum()
+}
+
+/// Index of a piece in the input layer from the point of view of `perspective`
+#[inline(always)]
+fn feature(perspective: Color, color: Color, piece: Piece, square: Square) -> usize {
+    let square = match perspective {
+        Color::White => square.to_index(),
+        Color::Black => square.to_index() ^ 56,
+    };
+    let side = usize::from(color != perspective);
+
+    side * 384 + piece.to_index() * 64 + square
+}
+
+/// Hidden layer values of both perspectives, indexed by `Color::to_index`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Accumulator {
+    values: [[i16; HIDDEN]; 2],
+}
+
+impl Accumulator {
+    /// Builds the accumulator of a position from scratch
+    #[must_use]
+    pub fn new<B: Position>(network: &Network, board: &B) -> Self {
+        let mut acc = Self {
+            values: [network.feature_bias; 2],
+        };
+
+        for color in ALL_COLORS {
+            for piece in ALL_PIECES {
+                for square in *board.color_combined(color) & *board.pieces(piece) {
+                    acc.add(network, color, piece, square);
+                }
+            }
+        }
+
+        acc
+    }
+
+    #[inline(always)]
+    fn add(&mut self, network: &Network, color: Color, piece: Piece, square: Square) {
+        for perspective in ALL_COLORS {
+            let weights = &network.feature_weights[feature(perspective, color, piece, square)];
+            for (x, w) in self.values[perspective.to_index()].iter_mut().zip(weights) {
+                *x += w;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, network: &Network, color: Color, piece: Piece, square: Square) {
+        for perspective in ALL_COLORS {
+            let weights = &network.feature_weights[feature(perspective, color, piece, square)];
+            for (x, w) in self.values[perspective.to_index()].iter_mut().zip(weights) {
+                *x -= w;
+            }
+        }
+    }
+
+    /// Returns the accumulator after `mv` is played on `board`, like `Board::make_move_new`
+    #[must_use]
+    pub fn make_move_new<B: Position>(&self, network: &Network, board: &B, mv: ChessMove) -> Self {
+        let mut acc = *self;
+        let us = board.side_to_move();
+        let (source, dest) = (mv.get_source(), mv.get_dest());
+        let piece = board.piece_on(source).expect("no piece on move source");
+
+        // Wherever the king and rook start, they end up on fixed files
+        if let Some(rook_square) = board.castling_rook(mv) {
+            let (king, rook) = if rook_square.get_file() > source.get_file() {
+                (File::G, File::F)
+            } else {
+                (File::C, File::D)
+            };
+            let rank = source.get_rank();
+            acc.remove(network, us, Piece::King, source);
+            acc.remove(network, us, Piece::Rook, rook_square);
+            acc.add(network, us, Piece::King, Square::make_square(rank, king));
+            acc.add(network, us, Piece::Rook, Square::make_square(rank, rook));
+            return acc;
+        }
+
+        acc.remove(network, us, piece, source);
+        acc.add(network, us, mv.get_promotion().unwrap_or(piece), dest);
+
+        if let Some(captured) = board.piece_on(dest) {
+            acc.remove(network, !us, captured, dest);
+        } else if piece == Piece::Pawn && source.get_file() != dest.get_file() {
+            // En passant
+            let square = Square::make_square(source.get_rank(), dest.get_file());
+            acc.remove(network, !us, Piece::Pawn, square);
+        }
+
+        acc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    fn random_network() -> super::Network {
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let bytes: Vec<u8> = (0..super::Network::SIZE / 2)
+            .flat_map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                // Keep the weights small so the accumulator does not overflow
+                ((state % 32) as i16 - 8).to_le_bytes()
+            })
+            .collect();
+
+        super::Network::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_incremental() {
+        use super::*;
+        use crate::board::Board;
+        use std::str::FromStr;
+
+        let network = random_network();
+
+        // Castling, en passant, captures and promotions
+        for (fen, moves) in [
+            (
+                "r3k2r/pppq1ppp/2n2n2/3pp3/3PP3/2N2N2/PPPQ1PPP/R3K2R w KQkq - 0 1",
+                "e1g1 e8c8 d4e5 d5e4 f3g5 d7g4",
+            ),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6 e8d8 d6d7"),
+            (
+                "1n2k3/P7/8/8/8/8/6p1/4K2R b K - 0 1",
+                "g2h1q e1e2 h1h3 a7b8n",
+            ),
+        ] {
+            let mut board = Board::from_str(fen).unwrap();
+            let mut acc = Accumulator::new(&network, &board);
+            // The `chess` crate writes castling as a two square king move
+            let mut other = chess::Board::from_str(fen).unwrap();
+            let mut other_acc = acc;
+
+            for mv in moves.split(' ') {
+                let other_mv = ChessMove::from_str(mv).unwrap();
+                let mv = board.parse_uci(mv).expect("legal move");
+
+                acc = acc.make_move_new(&network, &board, mv);
+                board = board.make_move_new(mv);
+                assert_eq!(acc, Accumulator::new(&network, &board), "{mv} on {fen}");
+
+                other_acc = other_acc.make_move_new(&network, &other, other_mv);
+                other = other.make_move_new(other_mv);
+                assert_eq!(other_acc, acc, "{mv} on {fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_symmetry() {
+        use super::*;
+        use crate::board::Board;
+        use std::str::FromStr;
+
+        let network = random_network();
+
+        // A position and its color flipped mirror evaluate the same for the side to move
+        let board =
+            Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let mirror =
+            Board::from_str("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3")
+                .unwrap();
+
+        let a = network.evaluate(&Accumulator::new(&network, &board), Color::White);
+        let b = network.evaluate(&Accumulator::new(&network, &mirror), Color::Black);
+        assert_eq!(a, b);
+
+        assert!(Network::from_bytes(&[0; 10]).is_err());
+    }
+
+    #[test]
+    fn test_known_endgames() {
+        use crate::board::Board;
+        use crate::engine::Engine;
+        use crate::time::TimeManager;
+        use crate::utils::History;
+        use std::str::FromStr;
+        use std::sync::Arc;
+
+        // A drawn rook pawn endgame is scored by the endgame evaluation, not by the network
+        let board = Board::from_str("k7/8/1K6/P7/8/8/8/8 w - - 0 1").unwrap();
+        let mut engine = Engine::new(1);
+        engine.nnue = Some(Arc::new(random_network()));
+        let time = TimeManager {
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        let (_, score) = engine.search(board, &time, History::new());
+        assert_eq!(score, 0);
+    }
+}
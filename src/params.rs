@@ -0,0 +1,346 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::ops::{Index, IndexMut};
+use std::str::FromStr;
+
+use const_for::const_for;
+
+use crate::{eval, tables};
+
+/// Which half of the tapered evaluation a parameter belongs to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Mg,
+    Eg,
+}
+
+/// A named run of parameters in the parameter vector
+#[derive(Clone, Copy, Debug)]
+pub struct ParamGroup {
+    pub name: &'static str,
+    pub offset: usize,
+    pub len: usize,
+    pub phase: Phase,
+}
+
+/// Declares the parameter vector.
+/// Every group gets an offset constant of the same name, its length, phase and default values.
+macro_rules! params {
+    ($($name:ident: [$len:expr; $phase:ident] = $default:expr,)*) => {
+        #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+        enum Group {
+            $($name,)*
+        }
+
+        const LENGTHS: &[usize] = &[$($len,)*];
+
+        const OFFSETS: [usize; LENGTHS.len()] = {
+            let mut offsets = [0; LENGTHS.len()];
+            const_for!(i in 1..LENGTHS.len() => {
+                offsets[i] = offsets[i - 1] + LENGTHS[i - 1];
+            });
+            offsets
+        };
+
+        /// Number of evaluation parameters
+        pub const NUM_PARAMS: usize = OFFSETS[LENGTHS.len() - 1] + LENGTHS[LENGTHS.len() - 1];
+
+        $(pub const $name: usize = OFFSETS[Group::$name as usize];)*
+
+        /// All parameter groups in the order they are stored
+        pub const PARAM_GROUPS: &[ParamGroup] = &[$(ParamGroup {
+            name: stringify!($name),
+            offset: $name,
+            len: $len,
+            phase: Phase::$phase,
+        },)*];
+
+        /// Defaults of all parameters, in the same order
+        pub const DEFAULT_VALUES: [i32; NUM_PARAMS] = {
+            let mut values = [0; NUM_PARAMS];
+            $(
+                let default: &[i32] = $default;
+                const_for!(i in 0..$len => {
+                    values[$name + i] = default[i];
+                });
+            )*
+            values
+        };
+    };
+}
+
+params! {
+    MG_VALUE: [6; Mg] = &tables::MG_VALUE,
+    EG_VALUE: [6; Eg] = &tables::EG_VALUE,
+    MG_PAWN_TABLE: [64; Mg] = &tables::MG_PAWN_TABLE,
+    MG_KNIGHT_TABLE: [64; Mg] = &tables::MG_KNIGHT_TABLE,
+    MG_BISHOP_TABLE: [64; Mg] = &tables::MG_BISHOP_TABLE,
+    MG_ROOK_TABLE: [64; Mg] = &tables::MG_ROOK_TABLE,
+    MG_QUEEN_TABLE: [64; Mg] = &tables::MG_QUEEN_TABLE,
+    MG_KING_TABLE: [64; Mg] = &tables::MG_KING_TABLE,
+    EG_PAWN_TABLE: [64; Eg] = &tables::EG_PAWN_TABLE,
+    EG_KNIGHT_TABLE: [64; Eg] = &tables::EG_KNIGHT_TABLE,
+    EG_BISHOP_TABLE: [64; Eg] = &tables::EG_BISHOP_TABLE,
+    EG_ROOK_TABLE: [64; Eg] = &tables::EG_ROOK_TABLE,
+    EG_QUEEN_TABLE: [64; Eg] = &tables::EG_QUEEN_TABLE,
+    EG_KING_TABLE: [64; Eg] = &tables::EG_KING_TABLE,
+    MG_KNIGHT_MOBILITY: [9; Mg] = &tables::MG_KNIGHT_MOBILITY,
+    MG_BISHOP_MOBILITY: [14; Mg] = &tables::MG_BISHOP_MOBILITY,
+    MG_ROOK_MOBILITY: [15; Mg] = &tables::MG_ROOK_MOBILITY,
+    MG_QUEEN_MOBILITY: [28; Mg] = &tables::MG_QUEEN_MOBILITY,
+    EG_KNIGHT_MOBILITY: [9; Eg] = &tables::EG_KNIGHT_MOBILITY,
+    EG_BISHOP_MOBILITY: [14; Eg] = &tables::EG_BISHOP_MOBILITY,
+    EG_ROOK_MOBILITY: [15; Eg] = &tables::EG_ROOK_MOBILITY,
+    EG_QUEEN_MOBILITY: [28; Eg] = &tables::EG_QUEEN_MOBILITY,
+    KING_SAFETY_TABLE: [100; Mg] = &tables::KING_SAFETY_TABLE,
+    MG_PASSED_PAWN_BONUS: [8; Mg] = &eval::MG_PASSED_PAWN_BONUS,
+    EG_PASSED_PAWN_BONUS: [8; Eg] = &eval::EG_PASSED_PAWN_BONUS,
+    MG_ISOLATED_PAWN_PENALTY: [1; Mg] = &[eval::MG_ISOLATED_PAWN_PENALTY],
+    EG_ISOLATED_PAWN_PENALTY: [1; Eg] = &[eval::EG_ISOLATED_PAWN_PENALTY],
+    MG_DOUBLED_PAWN_PENALTY: [1; Mg] = &[eval::MG_DOUBLED_PAWN_PENALTY],
+    EG_DOUBLED_PAWN_PENALTY: [1; Eg] = &[eval::EG_DOUBLED_PAWN_PENALTY],
+    MG_BACKWARD_PAWN_PENALTY: [1; Mg] = &[eval::MG_BACKWARD_PAWN_PENALTY],
+    EG_BACKWARD_PAWN_PENALTY: [1; Eg] = &[eval::EG_BACKWARD_PAWN_PENALTY],
+    MG_CONNECTED_PAWN_BONUS: [8; Mg] = &eval::MG_CONNECTED_PAWN_BONUS,
+    EG_CONNECTED_PAWN_BONUS: [8; Eg] = &eval::EG_CONNECTED_PAWN_BONUS,
+    PAWN_SHELTER_BONUS: [8; Mg] = &eval::PAWN_SHELTER_BONUS,
+    PAWN_STORM_PENALTY: [8; Mg] = &eval::PAWN_STORM_PENALTY,
+    KING_OPEN_FILE_PENALTY: [1; Mg] = &[eval::KING_OPEN_FILE_PENALTY],
+    KING_HALF_OPEN_FILE_PENALTY: [1; Mg] = &[eval::KING_HALF_OPEN_FILE_PENALTY],
+    MG_BISHOP_PAIR_BONUS: [1; Mg] = &[eval::MG_BISHOP_PAIR_BONUS],
+    EG_BISHOP_PAIR_BONUS: [1; Eg] = &[eval::EG_BISHOP_PAIR_BONUS],
+    MG_ROOK_OPEN_FILE_BONUS: [1; Mg] = &[eval::MG_ROOK_OPEN_FILE_BONUS],
+    EG_ROOK_OPEN_FILE_BONUS: [1; Eg] = &[eval::EG_ROOK_OPEN_FILE_BONUS],
+    MG_ROOK_HALF_OPEN_FILE_BONUS: [1; Mg] = &[eval::MG_ROOK_HALF_OPEN_FILE_BONUS],
+    EG_ROOK_HALF_OPEN_FILE_BONUS: [1; Eg] = &[eval::EG_ROOK_HALF_OPEN_FILE_BONUS],
+    MG_QUEEN_OPEN_FILE_BONUS: [1; Mg] = &[eval::MG_QUEEN_OPEN_FILE_BONUS],
+    EG_QUEEN_OPEN_FILE_BONUS: [1; Eg] = &[eval::EG_QUEEN_OPEN_FILE_BONUS],
+    MG_QUEEN_HALF_OPEN_FILE_BONUS: [1; Mg] = &[eval::MG_QUEEN_HALF_OPEN_FILE_BONUS],
+    EG_QUEEN_HALF_OPEN_FILE_BONUS: [1; Eg] = &[eval::EG_QUEEN_HALF_OPEN_FILE_BONUS],
+    MG_ROOK_ON_SEVENTH_BONUS: [1; Mg] = &[eval::MG_ROOK_ON_SEVENTH_BONUS],
+    EG_ROOK_ON_SEVENTH_BONUS: [1; Eg] = &[eval::EG_ROOK_ON_SEVENTH_BONUS],
+    MG_QUEEN_ON_SEVENTH_BONUS: [1; Mg] = &[eval::MG_QUEEN_ON_SEVENTH_BONUS],
+    EG_QUEEN_ON_SEVENTH_BONUS: [1; Eg] = &[eval::EG_QUEEN_ON_SEVENTH_BONUS],
+    MG_KNIGHT_OUTPOST_BONUS: [1; Mg] = &[eval::MG_KNIGHT_OUTPOST_BONUS],
+    EG_KNIGHT_OUTPOST_BONUS: [1; Eg] = &[eval::EG_KNIGHT_OUTPOST_BONUS],
+    MG_BISHOP_OUTPOST_BONUS: [1; Mg] = &[eval::MG_BISHOP_OUTPOST_BONUS],
+    EG_BISHOP_OUTPOST_BONUS: [1; Eg] = &[eval::EG_BISHOP_OUTPOST_BONUS],
+    MG_TRAPPED_BISHOP_PENALTY: [1; Mg] = &[eval::MG_TRAPPED_BISHOP_PENALTY],
+    EG_TRAPPED_BISHOP_PENALTY: [1; Eg] = &[eval::EG_TRAPPED_BISHOP_PENALTY],
+    MG_TRAPPED_ROOK_PENALTY: [1; Mg] = &[eval::MG_TRAPPED_ROOK_PENALTY],
+    EG_TRAPPED_ROOK_PENALTY: [1; Eg] = &[eval::EG_TRAPPED_ROOK_PENALTY],
+    TEMPO_BONUS: [1; Mg] = &[eval::TEMPO_BONUS],
+}
+
+/// Piece square tables, indexed by `Piece::to_index`
+pub const MG_PST: [usize; 6] = [
+    MG_PAWN_TABLE,
+    MG_KNIGHT_TABLE,
+    MG_BISHOP_TABLE,
+    MG_ROOK_TABLE,
+    MG_QUEEN_TABLE,
+    MG_KING_TABLE,
+];
+pub const EG_PST: [usize; 6] = [
+    EG_PAWN_TABLE,
+    EG_KNIGHT_TABLE,
+    EG_BISHOP_TABLE,
+    EG_ROOK_TABLE,
+    EG_QUEEN_TABLE,
+    EG_KING_TABLE,
+];
+
+/// Mobility tables, indexed by `Piece::to_index`. Pawns and kings have none.
+pub const MG_MOBILITY: [usize; 6] = [
+    0,
+    MG_KNIGHT_MOBILITY,
+    MG_BISHOP_MOBILITY,
+    MG_ROOK_MOBILITY,
+    MG_QUEEN_MOBILITY,
+    0,
+];
+pub const EG_MOBILITY: [usize; 6] = [
+    0,
+    EG_KNIGHT_MOBILITY,
+    EG_BISHOP_MOBILITY,
+    EG_ROOK_MOBILITY,
+    EG_QUEEN_MOBILITY,
+    0,
+];
+
+/// Evaluation weights as one flat vector, indexed by the offsets in this module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalParams {
+    pub values: [i32; NUM_PARAMS],
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            values: DEFAULT_VALUES,
+        }
+    }
+}
+
+impl Index<usize> for EvalParams {
+    type Output = i32;
+
+    #[inline(always)]
+    fn index(&self, index: usize) -> &i32 {
+        &self.values[index]
+    }
+}
+
+impl IndexMut<usize> for EvalParams {
+    #[inline(always)]
+    fn index_mut(&mut self, index: usize) -> &mut i32 {
+        &mut self.values[index]
+    }
+}
+
+impl EvalParams {
+    /// Loads parameters from a file in the format written by `Display`
+    /// # Errors
+    /// when the file cannot be read or parsed
+    pub fn load(path: &str) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("{path}: {e}"))?
+            .parse()
+    }
+}
+
+/// Dumps the parameters in a TOML-like format, one `NAME = value` or `NAME = [...]` per group
+impl Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for group in PARAM_GROUPS {
+            let values = &self.values[group.offset..group.offset + group.len];
+
+            if group.len == 1 {
+                writeln!(f, "{} = {}", group.name, values[0])?;
+                continue;
+            }
+
+            writeln!(f, "{} = [", group.name)?;
+            for row in values.chunks(if group.len == 64 { 8 } else { 10 }) {
+                let row: Vec<String> = row.iter().map(|x| format!("{x:4},")).collect();
+                writeln!(f, "   {}", row.join(""))?;
+            }
+            writeln!(f, "]")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the format written by `Display`.
+/// Groups that are missing keep their default values, `#` starts a comment.
+impl FromStr for EvalParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+
+        let text: String = s
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let mut rest = text.trim();
+
+        while !rest.is_empty() {
+            let (name, value) = rest
+                .split_once('=')
+                .ok_or_else(|| format!("expected `=` after `{rest}`"))?;
+            let name = name.trim();
+            let value = value.trim_start();
+
+            let (values, remaining) = if let Some(array) = value.strip_prefix('[') {
+                array
+                    .split_once(']')
+                    .ok_or_else(|| format!("unterminated array for {name}"))?
+            } else {
+                value.split_once('\n').unwrap_or((value, ""))
+            };
+            rest = remaining.trim();
+
+            let group = PARAM_GROUPS
+                .iter()
+                .find(|g| g.name == name)
+                .ok_or_else(|| format!("unknown parameter {name}"))?;
+            let values = values
+                .split(',')
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(|x| {
+                    x.parse()
+                        .map_err(|_| format!("invalid value `{x}` for {name}"))
+                })
+                .collect::<Result<Vec<i32>, String>>()?;
+
+            if values.len() != group.len {
+                return Err(format!(
+                    "{name} expects {} values, got {}",
+                    group.len,
+                    values.len()
+                ));
+            }
+            params.values[group.offset..group.offset + group.len].copy_from_slice(&values);
+        }
+
+        Ok(params)
+    }
+}
+
+/// Phase of the parameter at `index`
+#[must_use]
+pub fn phase_of(index: usize) -> Phase {
+    PARAM_GROUPS
+        .iter()
+        .find(|g| (g.offset..g.offset + g.len).contains(&index))
+        .expect("parameter index out of range")
+        .phase
+}
+
+mod test {
+    #[test]
+    fn test_groups() {
+        use super::*;
+
+        // Groups are laid out back to back
+        let mut offset = 0;
+        for group in PARAM_GROUPS {
+            assert_eq!(group.offset, offset, "{}", group.name);
+            offset += group.len;
+        }
+        assert_eq!(offset, NUM_PARAMS);
+
+        let params = EvalParams::default();
+        assert_eq!(params[MG_VALUE + 4], 1025);
+        assert_eq!(params[TEMPO_BONUS], 10);
+        assert_eq!(phase_of(EG_KING_TABLE + 63), Phase::Eg);
+    }
+
+    #[test]
+    fn test_dump_load() {
+        use super::*;
+
+        let mut params = EvalParams::default();
+        params[MG_VALUE] = 100;
+        params[EG_KING_TABLE + 63] = -7;
+        params[TEMPO_BONUS] = 15;
+
+        let dump = params.to_string();
+        assert_eq!(dump.parse::<EvalParams>(), Ok(params));
+
+        // Missing groups keep their defaults
+        let partial: EvalParams = "# Only the tempo\nTEMPO_BONUS = 20 # comment\n"
+            .parse()
+            .unwrap();
+        assert_eq!(partial[TEMPO_BONUS], 20);
+        assert_eq!(partial[MG_VALUE], EvalParams::default()[MG_VALUE]);
+
+        assert!("FOO = 1".parse::<EvalParams>().is_err());
+        assert!("MG_VALUE = [1, 2]".parse::<EvalParams>().is_err());
+        assert!("TEMPO_BONUS = x".parse::<EvalParams>().is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use chess::{CacheTable, Color, Piece};
+
+use crate::position::Position;
+
+use crate::tables::PAWN_ZOBRIST;
+
+/// Number of entries in the pawn table, has to be a power of two
+const PAWN_TABLE_ENTRIES: usize = 1 << 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Debug, Default)]
+/// Cached pawn structure evaluation, scores are from white's point of view
+pub struct PawnEntry {
+    pub mg: i32,
+    pub eg: i32,
+    /// Pawn shield, storm and open file midgame score for each color with the king on a given file
+    pub shelter: [[i16; 8]; 2],
+}
+
+pub struct PawnTable {
+    t: CacheTable<PawnEntry>,
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PawnTable {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            t: CacheTable::new(PAWN_TABLE_ENTRIES, PawnEntry::default()),
+        }
+    }
+
+    pub fn set(&mut self, key: u64, a: PawnEntry) {
+        self.t.add(key, a);
+    }
+
+    #[must_use]
+    pub fn get(&self, key: u64) -> Option<PawnEntry> {
+        self.t.get(key)
+    }
+}
+
+/// Zobrist key of the pawns only.
+#[must_use]
+pub fn pawn_key<B: Position>(board: &B) -> u64 {
+    let pawns = board.pieces(Piece::Pawn);
+    let mut key = 0;
+
+    for square in pawns & board.color_combined(Color::White) {
+        key ^= PAWN_ZOBRIST[0][square.to_index()];
+    }
+    for square in pawns & board.color_combined(Color::Black) {
+        key ^= PAWN_ZOBRIST[1][square.to_index()];
+    }
+
+    key
+}
+
+mod test {
+    #[test]
+    fn test_pawn_key() {
+        use super::pawn_key;
+        use crate::board::Board;
+        use chess::ChessMove;
+        use std::str::FromStr;
+
+        let b = Board::default();
+
+        // Piece moves do not change the key
+        let knight = b.make_move_new(ChessMove::from_str("g1f3").unwrap());
+        assert_eq!(pawn_key(&b), pawn_key(&knight));
+
+        // Pawn moves do
+        let pawn = b.make_move_new(ChessMove::from_str("e2e4").unwrap());
+        assert_ne!(pawn_key(&b), pawn_key(&pawn));
+
+        // Colors are hashed separately
+        let white = Board::from_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let black = Board::from_str("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1").unwrap();
+        assert_ne!(pawn_key(&white), pawn_key(&black));
+    }
+
+    #[test]
+    fn test_cached_eval() {
+        use crate::board::Board;
+        use crate::eval::{eval, eval_trace, Term};
+        use crate::params::EvalParams;
+        use std::str::FromStr;
+
+        let params = EvalParams::default();
+        let mut pawns = super::PawnTable::new();
+
+        // Same pawns, the d5 pawn is passed unless an enemy piece is in front of or beside it
+        for (fen, passed) in [
+            ("r6k/pp6/8/3P4/8/8/PP6/R3K3 w - - 0 1", true),
+            ("r6k/pp1n4/8/3P4/8/8/PP6/R3K3 w - - 0 1", false),
+            ("r6k/pp6/2n5/3P4/8/8/PP6/R3K3 w - - 0 1", false),
+            ("r6k/pp6/8/3P4/8/8/PP6/R2NK3 w - - 0 1", true),
+            ("r6k/pp6/8/3PN3/8/8/PP6/R3K3 w - - 0 1", true),
+        ] {
+            let board = Board::from_str(fen).unwrap();
+            let trace = eval_trace(&board, &params);
+            assert_eq!(trace.total(Term::PassedPawns) != (0, 0), passed, "{fen}");
+
+            // Cache hits give the uncached score
+            assert_eq!(eval(&board, &params, &mut pawns), trace.score, "{fen}");
+            assert_eq!(eval(&board, &params, &mut pawns), trace.score, "{fen}");
+        }
+    }
+}
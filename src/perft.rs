@@ -0,0 +1,124 @@
+//! Perft, counting the leaf nodes of the move generation tree
+//! <https://www.chessprogramming.org/Perft>
+//!
+//! Moves come from `MoveGenOrdered`, so this checks the staged generator the search uses.
+
+use std::fmt::Write;
+use std::time::Instant;
+
+use chess::ChessMove;
+
+use crate::board::Board;
+use crate::utils::{MoveGenOrdered, SearchInfo};
+
+fn perft_inner(board: &Board, depth: u8, sinfo: &SearchInfo) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut movegen = MoveGenOrdered::new(board, sinfo, 0, None, false);
+    let mut nodes = 0;
+    while let Some(mv) = movegen.pick_next(sinfo) {
+        nodes += perft_inner(&board.make_move_new(mv), depth - 1, sinfo);
+    }
+    nodes
+}
+
+/// Number of leaf nodes `depth` plies from `board`
+#[must_use]
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    perft_inner(board, depth, &SearchInfo::default())
+}
+
+/// Perft of every legal move of `board`
+#[must_use]
+pub fn divide(board: &Board, depth: u8) -> Vec<(ChessMove, u64)> {
+    let sinfo = SearchInfo::default();
+    let mut movegen = MoveGenOrdered::new(board, &sinfo, 0, None, false);
+    let mut moves = vec![];
+    while let Some(mv) = movegen.pick_next(&sinfo) {
+        moves.push((mv, perft(&board.make_move_new(mv), depth.saturating_sub(1))));
+    }
+    moves
+}
+
+/// Output of `go perft <depth>`, the divide sorted by move followed by the total
+#[must_use]
+pub fn report(board: &Board, depth: u8, chess960: bool) -> String {
+    let start = Instant::now();
+    let mut moves: Vec<(String, u64)> = divide(board, depth)
+        .into_iter()
+        .map(|(mv, nodes)| (board.format_move(mv, chess960), nodes))
+        .collect();
+    moves.sort();
+
+    let total: u64 = moves.iter().map(|(_, nodes)| nodes).sum();
+    let time = start.elapsed().as_millis();
+
+    let mut out = String::new();
+    for (mv, nodes) in &moves {
+        let _ = writeln!(out, "{mv}: {nodes}");
+    }
+    let _ = writeln!(out, "\nNodes searched: {total}");
+    let _ = writeln!(
+        out,
+        "info string time {time} nps {}",
+        1000 * u128::from(total) / (time + 1)
+    );
+    out
+}
+
+mod test {
+    #[test]
+    fn test_perft() {
+        use super::*;
+        use std::str::FromStr;
+
+        // https://www.chessprogramming.org/Perft_Results
+        for (fen, depth, nodes) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                4,
+                197_281,
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                3,
+                97_862,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43_238),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                3,
+                9_467,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                3,
+                62_379,
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                3,
+                89_890,
+            ),
+        ] {
+            let board = Board::from_str(fen).unwrap();
+            assert_eq!(perft(&board, depth), nodes, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_divide() {
+        use super::*;
+
+        let board = Board::default();
+        let moves = divide(&board, 3);
+        assert_eq!(moves.len(), 20);
+        assert_eq!(moves.iter().map(|(_, n)| n).sum::<u64>(), 8_902);
+
+        let out = report(&board, 2, false);
+        assert!(out.contains("e2e4: 20\n"));
+        assert!(out.contains("Nodes searched: 400\n"));
+    }
+}
@@ -0,0 +1,262 @@
+//! PGN and SAN parsing, enough to read game collections
+//! <https://www.chessprogramming.org/Portable_Game_Notation>
+//!
+//! Comments, variations and NAGs are skipped, only the main line is kept.
+
+use chess::{ChessMove, File, Piece, Rank, Square};
+
+use crate::board::Board;
+use crate::position::Position;
+
+/// Game termination markers, the last one is an unfinished game
+pub const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    /// Main line in SAN
+    pub moves: Vec<String>,
+    /// One of [`RESULTS`], from the termination marker or else the `Result` tag
+    pub result: String,
+}
+
+impl Game {
+    #[must_use]
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position of the `FEN` tag or the start position
+    /// # Errors
+    /// when the FEN is invalid
+    pub fn start(&self) -> Result<Board, String> {
+        self.tag("FEN")
+            .map_or_else(|| Ok(Board::default()), Board::from_fen)
+    }
+}
+
+/// Splits the text into games
+#[must_use]
+pub fn parse_pgn(text: &str) -> Vec<Game> {
+    let mut games = vec![];
+    let mut game = Game::default();
+    let mut chars = text.chars().peekable();
+    // Nesting depth of variations
+    let mut depth = 0;
+
+    let mut finish = |game: &mut Game| {
+        let game = std::mem::take(game);
+        if !game.moves.is_empty() || !game.tags.is_empty() {
+            games.push(game);
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if depth == 0 => {
+                // Tags after the moves start the next game
+                if !game.moves.is_empty() {
+                    finish(&mut game);
+                }
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some((name, value)) = tag.split_once(char::is_whitespace) {
+                    let value = value.trim().trim_matches('"').replace("\\\"", "\"");
+                    if name == "Result" && game.result.is_empty() {
+                        game.result.clone_from(&value);
+                    }
+                    game.tags.push((name.to_string(), value));
+                }
+            }
+            '{' => chars.by_ref().take_while(|&c| c != '}').for_each(drop),
+            ';' | '%' => chars.by_ref().take_while(|&c| c != '\n').for_each(drop),
+            '(' => depth += 1,
+            ')' => depth = (depth - 1).max(0),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}();[".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if depth > 0 {
+                    continue;
+                }
+
+                if RESULTS.contains(&token.as_str()) {
+                    game.result = token;
+                    finish(&mut game);
+                    continue;
+                }
+
+                // Move numbers can be glued to the move, like `1.e4`
+                let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if !san.is_empty() && !san.starts_with('$') {
+                    game.moves.push(san.to_string());
+                }
+            }
+        }
+    }
+    finish(&mut game);
+
+    games
+}
+
+/// Finds the legal move written in SAN, `None` if there is none or it is ambiguous.
+/// Checks and annotations are ignored, castling may be written with zeros and long algebraic
+/// like `e2-e4` is accepted too.
+#[must_use]
+pub fn parse_san<B: Position>(board: &B, san: &str) -> Option<ChessMove> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal = board.legal_moves();
+
+    if let Some(kingside) = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    } {
+        return legal.into_iter().find(|&mv| {
+            board
+                .castling_rook(mv)
+                .is_some_and(|rook| (rook.get_file() > mv.get_source().get_file()) == kingside)
+        });
+    }
+
+    let piece_of = |c| match c {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    };
+
+    // The promotion is usually written as `=Q`, sometimes only as `Q`
+    let (san, promotion) = match san.char_indices().last()? {
+        (i, c) if piece_of(c).is_some() => (san[..i].trim_end_matches('='), piece_of(c)),
+        _ => (san, None),
+    };
+
+    let mut chars: Vec<char> = san.chars().filter(|&c| c != 'x' && c != '-').collect();
+    let piece = match chars.first().copied().and_then(piece_of) {
+        Some(piece) => {
+            chars.remove(0);
+            piece
+        }
+        None => Piece::Pawn,
+    };
+
+    if chars.len() < 2 {
+        return None;
+    }
+    let (hint, dest) = chars.split_at(chars.len() - 2);
+    let dest = Square::make_square(rank(dest[1])?, file(dest[0])?);
+
+    let mut found = legal.into_iter().filter(|&mv| {
+        let source = mv.get_source();
+        mv.get_dest() == dest
+            && mv.get_promotion() == promotion
+            && board.piece_on(source) == Some(piece)
+            && !board.is_castling(mv)
+            && hint
+                .iter()
+                .all(|&c| file(c) == Some(source.get_file()) || rank(c) == Some(source.get_rank()))
+    });
+
+    match (found.next(), found.next()) {
+        (Some(mv), None) => Some(mv),
+        _ => None,
+    }
+}
+
+fn file(c: char) -> Option<File> {
+    ('a'..='h')
+        .contains(&c)
+        .then(|| File::from_index(c as usize - 'a' as usize))
+}
+
+fn rank(c: char) -> Option<Rank> {
+    ('1'..='8')
+        .contains(&c)
+        .then(|| Rank::from_index(c as usize - '1' as usize))
+}
+
+mod test {
+    #[test]
+    fn test_parse_pgn() {
+        use super::*;
+
+        let pgn = r#"
+[Event "Test"]
+[White "A"]
+[Result "1-0"]
+
+1. e4 {best by test} e5 2.Nf3 (2. f4 exf4 (2... d5)) Nc6 $1 3. Bb5 ; Ruy Lopez
+a6 1-0
+
+[Event "Second"]
+[FEN "4k3/8/8/8/8/8/8/4K2R w K - 0 1"]
+
+1. O-O Kd7 *
+"#;
+        let games = parse_pgn(pgn);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves, ["O-O", "Kd7"]);
+        assert_eq!(games[1].result, "*");
+        assert!(games[1].start().is_ok());
+
+        // Every game of the suite replays
+        let games = parse_pgn(include_str!("../silversuite.pgn"));
+        assert_eq!(games.len(), 50);
+        for game in games {
+            let mut board = game.start().unwrap();
+            for san in &game.moves {
+                let mv = parse_san(&board, san).unwrap_or_else(|| panic!("{san} on {board}"));
+                board = board.make_move_new(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_san() {
+        use super::*;
+        use std::str::FromStr;
+
+        for (fen, san, uci) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "Nf3",
+                "g1f3",
+            ),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "O-O-O", "e1a1"),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "0-0+", "e1h1"),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "Kf1", "e1f1"),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "Rhf1", "h1f1"),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "Raf1", ""),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "Rf1", "h1f1"),
+            ("4k3/8/8/8/8/R7/8/R3K3 w Q - 0 1", "Ra2", ""),
+            ("4k3/8/8/8/8/R7/8/R3K3 w Q - 0 1", "R1a2", "a1a2"),
+            ("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "axb8=N", "a7b8n"),
+            ("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a8Q", "a7a8q"),
+            ("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a8", ""),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "exd6", "e5d6"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "e5d6"),
+        ] {
+            let board = Board::from_str(fen).unwrap();
+            let expected = board.parse_uci(uci);
+            assert_eq!(parse_san(&board, san), expected, "{san} on {fen}");
+        }
+
+        // The `chess` crate writes castling as a two square king move
+        let board = chess::Board::from_str("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "O-O-O"), ChessMove::from_str("e1c1").ok());
+    }
+}
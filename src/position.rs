@@ -0,0 +1,324 @@
+//! The board interface search and eval are written against
+//!
+//! Implemented by the native [`Board`] and, as an adapter, by `chess::Board`. Everything is
+//! monomorphized, so the native board pays nothing for the abstraction.
+
+use chess::{BitBoard, CastleRights, ChessMove, Color, File, MoveGen, Piece, Square, EMPTY};
+
+use crate::board::{Board, MoveList, Undo};
+
+pub trait Position: Copy {
+    /// What `unmake_move` needs to restore
+    type Undo;
+
+    fn side_to_move(&self) -> Color;
+    fn piece_on(&self, square: Square) -> Option<Piece>;
+    fn color_on(&self, square: Square) -> Option<Color>;
+    fn pieces(&self, piece: Piece) -> &BitBoard;
+    fn color_combined(&self, color: Color) -> &BitBoard;
+    fn combined(&self) -> &BitBoard;
+    fn king_square(&self, color: Color) -> Square;
+    fn castle_rights(&self, color: Color) -> CastleRights;
+    /// Square a pawn of the side to move can capture en passant on
+    fn en_passant_target(&self) -> Option<Square>;
+    /// Pieces giving check to the side to move
+    fn checkers(&self) -> &BitBoard;
+    /// Pieces of the side to move that are pinned to their king
+    fn pinned(&self) -> BitBoard;
+    /// Zobrist key, only comparable between positions of the same implementation
+    fn get_hash(&self) -> u64;
+    /// Material + PST from white's point of view, (middlegame, endgame), when it is kept up to
+    /// date while making moves
+    fn psqt(&self) -> Option<(i32, i32)> {
+        None
+    }
+
+    /// Plays a legal move in place
+    fn make_move(&mut self, mv: ChessMove) -> Self::Undo;
+    /// Takes back `mv`, which `make_move` returned `undo` for
+    fn unmake_move(&mut self, mv: ChessMove, undo: &Self::Undo);
+    /// Plays a legal move on a copy
+    #[must_use]
+    fn make_move_new(&self, mv: ChessMove) -> Self;
+    fn legal_moves(&self) -> MoveList;
+    /// Legal captures, capturing promotions and en passant
+    fn generate_captures(&self, moves: &mut MoveList);
+    /// Legal non captures, including quiet promotions and castling
+    fn generate_quiets(&self, moves: &mut MoveList);
+    fn has_legal_moves(&self) -> bool {
+        !self.legal_moves().is_empty()
+    }
+    /// Whether a move, that might come from another position, is legal
+    fn legal(&self, mv: ChessMove) -> bool;
+
+    /// Whether the move takes a piece, including en passant but not castling
+    fn is_capture(&self, mv: ChessMove) -> bool;
+    /// The rook `mv` castles with, `None` when it does not castle
+    fn castling_rook(&self, mv: ChessMove) -> Option<Square>;
+    fn is_castling(&self, mv: ChessMove) -> bool {
+        self.castling_rook(mv).is_some()
+    }
+    /// UCI notation, castling as king takes rook when `chess960` is set
+    fn format_move(&self, mv: ChessMove, chess960: bool) -> String;
+}
+
+impl Position for Board {
+    type Undo = Undo;
+
+    #[inline(always)]
+    fn side_to_move(&self) -> Color {
+        Self::side_to_move(self)
+    }
+    #[inline(always)]
+    fn piece_on(&self, square: Square) -> Option<Piece> {
+        Self::piece_on(self, square)
+    }
+    #[inline(always)]
+    fn color_on(&self, square: Square) -> Option<Color> {
+        Self::color_on(self, square)
+    }
+    #[inline(always)]
+    fn pieces(&self, piece: Piece) -> &BitBoard {
+        Self::pieces(self, piece)
+    }
+    #[inline(always)]
+    fn color_combined(&self, color: Color) -> &BitBoard {
+        Self::color_combined(self, color)
+    }
+    #[inline(always)]
+    fn combined(&self) -> &BitBoard {
+        Self::combined(self)
+    }
+    #[inline(always)]
+    fn king_square(&self, color: Color) -> Square {
+        Self::king_square(self, color)
+    }
+    fn castle_rights(&self, color: Color) -> CastleRights {
+        Self::castle_rights(self, color)
+    }
+    fn en_passant_target(&self) -> Option<Square> {
+        Self::en_passant(self)
+    }
+    #[inline(always)]
+    fn checkers(&self) -> &BitBoard {
+        Self::checkers(self)
+    }
+    fn pinned(&self) -> BitBoard {
+        Self::pinned(self)
+    }
+    #[inline(always)]
+    fn get_hash(&self) -> u64 {
+        Self::get_hash(self)
+    }
+    #[inline(always)]
+    fn psqt(&self) -> Option<(i32, i32)> {
+        Some(Self::psqt(self))
+    }
+
+    #[inline(always)]
+    fn make_move(&mut self, mv: ChessMove) -> Undo {
+        Self::make_move(self, mv)
+    }
+    #[inline(always)]
+    fn unmake_move(&mut self, mv: ChessMove, undo: &Undo) {
+        Self::unmake_move(self, mv, undo);
+    }
+    fn make_move_new(&self, mv: ChessMove) -> Self {
+        Self::make_move_new(self, mv)
+    }
+    fn legal_moves(&self) -> MoveList {
+        Self::legal_moves(self)
+    }
+    fn generate_captures(&self, moves: &mut MoveList) {
+        Self::generate_captures(self, moves);
+    }
+    fn generate_quiets(&self, moves: &mut MoveList) {
+        Self::generate_quiets(self, moves);
+    }
+    fn has_legal_moves(&self) -> bool {
+        Self::has_legal_moves(self)
+    }
+    fn legal(&self, mv: ChessMove) -> bool {
+        Self::legal(self, mv)
+    }
+
+    #[inline(always)]
+    fn is_capture(&self, mv: ChessMove) -> bool {
+        Self::is_capture(self, mv)
+    }
+    fn castling_rook(&self, mv: ChessMove) -> Option<Square> {
+        Self::is_castling(self, mv).then_some(mv.get_dest())
+    }
+    #[inline(always)]
+    fn is_castling(&self, mv: ChessMove) -> bool {
+        Self::is_castling(self, mv)
+    }
+    fn format_move(&self, mv: ChessMove, chess960: bool) -> String {
+        Self::format_move(self, mv, chess960)
+    }
+}
+
+/// The `chess` crate only castles with the king on e and the rooks in the corners, written as
+/// the king moving two squares. It has no unmake, so the position before the move is the undo.
+impl Position for chess::Board {
+    type Undo = Self;
+
+    fn side_to_move(&self) -> Color {
+        Self::side_to_move(self)
+    }
+    fn piece_on(&self, square: Square) -> Option<Piece> {
+        Self::piece_on(self, square)
+    }
+    fn color_on(&self, square: Square) -> Option<Color> {
+        Self::color_on(self, square)
+    }
+    fn pieces(&self, piece: Piece) -> &BitBoard {
+        Self::pieces(self, piece)
+    }
+    fn color_combined(&self, color: Color) -> &BitBoard {
+        Self::color_combined(self, color)
+    }
+    fn combined(&self) -> &BitBoard {
+        Self::combined(self)
+    }
+    fn king_square(&self, color: Color) -> Square {
+        Self::king_square(self, color)
+    }
+    fn castle_rights(&self, color: Color) -> CastleRights {
+        Self::castle_rights(self, color)
+    }
+    /// The crate stores the square of the pawn that can be taken
+    fn en_passant_target(&self) -> Option<Square> {
+        Self::en_passant(*self).map(|pawn| pawn.uforward(Self::side_to_move(self)))
+    }
+    fn checkers(&self) -> &BitBoard {
+        Self::checkers(self)
+    }
+    fn pinned(&self) -> BitBoard {
+        *Self::pinned(self)
+    }
+    fn get_hash(&self) -> u64 {
+        Self::get_hash(self)
+    }
+
+    fn make_move(&mut self, mv: ChessMove) -> Self {
+        let undo = *self;
+        *self = Self::make_move_new(self, mv);
+        undo
+    }
+    fn unmake_move(&mut self, _: ChessMove, undo: &Self) {
+        *self = *undo;
+    }
+    fn make_move_new(&self, mv: ChessMove) -> Self {
+        Self::make_move_new(self, mv)
+    }
+    fn legal_moves(&self) -> MoveList {
+        MoveGen::new_legal(self).collect()
+    }
+    fn generate_captures(&self, moves: &mut MoveList) {
+        let mut movegen = MoveGen::new_legal(self);
+        movegen.set_iterator_mask(*self.color_combined(!self.side_to_move()) | en_passant(self));
+        moves.extend(movegen);
+    }
+    fn generate_quiets(&self, moves: &mut MoveList) {
+        let mut movegen = MoveGen::new_legal(self);
+        movegen.set_iterator_mask(!*self.combined() & !en_passant(self));
+        moves.extend(movegen);
+    }
+    fn legal(&self, mv: ChessMove) -> bool {
+        Self::legal(self, mv)
+    }
+
+    fn is_capture(&self, mv: ChessMove) -> bool {
+        let (source, dest) = (mv.get_source(), mv.get_dest());
+        self.piece_on(dest).is_some()
+            || (self.piece_on(source) == Some(Piece::Pawn) && source.get_file() != dest.get_file())
+    }
+    fn castling_rook(&self, mv: ChessMove) -> Option<Square> {
+        let (source, dest) = (mv.get_source(), mv.get_dest());
+        if self.piece_on(source) != Some(Piece::King)
+            || source
+                .get_file()
+                .to_index()
+                .abs_diff(dest.get_file().to_index())
+                != 2
+        {
+            return None;
+        }
+
+        let file = if dest.get_file() > source.get_file() {
+            File::H
+        } else {
+            File::A
+        };
+        Some(Square::make_square(source.get_rank(), file))
+    }
+    fn format_move(&self, mv: ChessMove, chess960: bool) -> String {
+        match self.castling_rook(mv) {
+            Some(rook) if chess960 => format!("{}{rook}", mv.get_source()),
+            _ => mv.to_string(),
+        }
+    }
+}
+
+/// The square a pawn lands on when capturing en passant
+fn en_passant(board: &chess::Board) -> BitBoard {
+    board
+        .en_passant_target()
+        .map_or(EMPTY, BitBoard::from_square)
+}
+
+mod test {
+    #[test]
+    fn test_backends_agree() {
+        use super::*;
+        use crate::engine::Engine;
+        use crate::time::TimeManager;
+        use crate::utils::History;
+        use std::str::FromStr;
+
+        fn captures<B: Position>(board: &B) -> Vec<String> {
+            let mut moves = MoveList::new();
+            board.generate_captures(&mut moves);
+            moves
+                .iter()
+                .map(|&mv| board.format_move(mv, false))
+                .collect()
+        }
+        fn quiets<B: Position>(board: &B) -> Vec<String> {
+            let mut moves = MoveList::new();
+            board.generate_quiets(&mut moves);
+            moves
+                .iter()
+                .map(|&mv| board.format_move(mv, false))
+                .collect()
+        }
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/1P4k1/8/8/8/8/5p2/4K2R w K - 0 1",
+        ] {
+            let native = Board::from_str(fen).unwrap();
+            let other = chess::Board::from_str(fen).unwrap();
+
+            // Same moves in the same order, apart from the castling notation
+            assert_eq!(captures(&native), captures(&other), "{fen}");
+            assert_eq!(quiets(&native), quiets(&other), "{fen}");
+
+            let time = TimeManager {
+                max_depth: Some(4),
+                ..Default::default()
+            };
+            let (mv, score) = Engine::new(16).search(native, &time, History::new());
+            let (other_mv, other_score) = Engine::new(16).search(other, &time, History::new());
+            assert_eq!(
+                Position::format_move(&native, mv, false),
+                other_mv.to_string(),
+                "{fen}"
+            );
+            assert_eq!(score, other_score, "{fen}");
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Registry of tunable search constants for SPSA tuning
+//! Every tunable is exposed as a UCI spin option, together with the scalar eval parameters.
+//! Search features that are off until they win an SPRT are check options.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use crate::params::{EvalParams, PARAM_GROUPS};
+
+/// A search constant that can be changed at runtime
+pub struct Tunable {
+    pub name: &'static str,
+    value: AtomicI32,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+}
+
+impl Tunable {
+    const fn new(name: &'static str, default: i32, min: i32, max: i32, step: i32) -> Self {
+        Self {
+            name,
+            value: AtomicI32::new(default),
+            default,
+            min,
+            max,
+            step,
+        }
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> i32 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, value: i32) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+}
+
+/// A search feature that can be switched on at runtime, off by default
+pub struct Switch {
+    pub name: &'static str,
+    value: AtomicBool,
+}
+
+impl Switch {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: AtomicBool::new(false),
+        }
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> bool {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, value: bool) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Orders quiets by countermove and history when set, off until it wins an SPRT
+pub static HISTORY_ORDERING: Switch = Switch::new("HISTORY_ORDERING");
+
+/// Searches quiet checks at the first qsearch ply when set, off until it wins an SPRT
+pub static QSEARCH_CHECKS: Switch = Switch::new("QSEARCH_CHECKS");
+
+pub static SWITCHES: &[&Switch] = &[&HISTORY_ORDERING, &QSEARCH_CHECKS];
+
+/// Minimum depth of the internal iterative reduction
+pub static IIR_DEPTH: Tunable = Tunable::new("IIR_DEPTH", 4, 2, 10, 1);
+/// Minimum depth of singular extensions
+pub static SINGULAR_DEPTH: Tunable = Tunable::new("SINGULAR_DEPTH", 6, 4, 12, 1);
+/// Margin below the hash score per depth for a move to count as singular
+pub static SINGULAR_MARGIN: Tunable = Tunable::new("SINGULAR_MARGIN", 2, 1, 8, 1);
+/// Estimated time factor between one depth and the next
+pub static ESTIMATE_TIME_BRANCHING_FACTOR: Tunable =
+    Tunable::new("ESTIMATE_TIME_BRANCHING_FACTOR", 8, 1, 32, 1);
+/// Fraction of the remaining time that a move may use
+pub static BOARD_TIME_DIVISOR: Tunable = Tunable::new("BOARD_TIME_DIVISOR", 20, 5, 60, 2);
+
+pub static TUNABLES: &[&Tunable] = &[
+    &IIR_DEPTH,
+    &SINGULAR_DEPTH,
+    &SINGULAR_MARGIN,
+    &ESTIMATE_TIME_BRANCHING_FACTOR,
+    &BOARD_TIME_DIVISOR,
+];
+
+/// Range and step of a spin option
+pub struct SpinOption {
+    pub name: &'static str,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+}
+
+/// Range around the default of the scalar eval parameters
+const EVAL_RANGE: i32 = 100;
+const EVAL_STEP: i32 = 4;
+
+/// All spin options, the search tunables followed by the scalar eval parameters
+#[must_use]
+pub fn spin_options() -> Vec<SpinOption> {
+    let defaults = EvalParams::default();
+
+    TUNABLES
+        .iter()
+        .map(|t| SpinOption {
+            name: t.name,
+            default: t.default,
+            min: t.min,
+            max: t.max,
+            step: t.step,
+        })
+        .chain(PARAM_GROUPS.iter().filter(|g| g.len == 1).map(|g| {
+            let default = defaults[g.offset];
+            SpinOption {
+                name: g.name,
+                default,
+                min: default - EVAL_RANGE,
+                max: default + EVAL_RANGE,
+                step: EVAL_STEP,
+            }
+        }))
+        .collect()
+}
+
+/// Sets a tunable or scalar eval parameter by name
+/// # Errors
+/// when there is no such option or the value is out of range
+pub fn set_option(name: &str, value: &str, params: &mut EvalParams) -> Result<(), String> {
+    if let Some(switch) = SWITCHES.iter().find(|s| s.name == name) {
+        let value = value
+            .parse()
+            .map_err(|_| format!("invalid value `{value}` for {name}"))?;
+        switch.set(value);
+        return Ok(());
+    }
+
+    let option = spin_options()
+        .into_iter()
+        .find(|o| o.name == name)
+        .ok_or_else(|| format!("unknown option {name}"))?;
+    let value: i32 = value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for {name}"))?;
+    if !(option.min..=option.max).contains(&value) {
+        return Err(format!(
+            "{name} must be between {} and {}",
+            option.min, option.max
+        ));
+    }
+
+    if let Some(tunable) = TUNABLES.iter().find(|t| t.name == name) {
+        tunable.set(value);
+    } else if let Some(group) = PARAM_GROUPS.iter().find(|g| g.name == name) {
+        params[group.offset] = value;
+    }
+
+    Ok(())
+}
+
+/// The `option` lines sent in response to `uci`
+#[must_use]
+pub fn uci_options() -> String {
+    let switches = SWITCHES.iter().fold(String::new(), |mut out, s| {
+        let _ = writeln!(out, "option name {} type check default false", s.name);
+        out
+    });
+
+    spin_options().iter().fold(switches, |mut out, o| {
+        let _ = writeln!(
+            out,
+            "option name {} type spin default {} min {} max {}",
+            o.name, o.default, o.min, o.max
+        );
+        out
+    })
+}
+
+/// OpenBench style parameter list, `name, int, default, min, max, c_end, r_end`
+#[must_use]
+pub fn openbench() -> String {
+    spin_options().iter().fold(String::new(), |mut out, o| {
+        let _ = writeln!(
+            out,
+            "{}, int, {}, {}, {}, {}, 0.002",
+            o.name, o.default, o.min, o.max, o.step
+        );
+        out
+    })
+}
+
+mod test {
+    #[test]
+    fn test_options() {
+        use super::*;
+        use crate::params::TEMPO_BONUS;
+
+        let mut params = EvalParams::default();
+        assert!(set_option("TEMPO_BONUS", "14", &mut params).is_ok());
+        assert_eq!(params[TEMPO_BONUS], 14);
+
+        assert!(set_option("TEMPO_BONUS", "1000", &mut params).is_err());
+        assert!(set_option("TEMPO_BONUS", "x", &mut params).is_err());
+        assert!(set_option("NOPE", "1", &mut params).is_err());
+        // Tables are too large for SPSA
+        assert!(set_option("MG_PAWN_TABLE", "1", &mut params).is_err());
+
+        // Setting a tunable to its default does not disturb searches running in parallel
+        assert!(set_option("BOARD_TIME_DIVISOR", "20", &mut params).is_ok());
+        assert_eq!(BOARD_TIME_DIVISOR.get(), 20);
+
+        // Switches are check options and are not tuned
+        assert!(set_option("HISTORY_ORDERING", "false", &mut params).is_ok());
+        assert!(!HISTORY_ORDERING.get());
+        assert!(set_option("HISTORY_ORDERING", "1", &mut params).is_err());
+        assert!(uci_options().contains("option name HISTORY_ORDERING type check default false"));
+        assert!(!openbench().contains("HISTORY_ORDERING"));
+        assert!(uci_options().contains("option name QSEARCH_CHECKS type check default false"));
+        assert!(!openbench().contains("QSEARCH_CHECKS"));
+
+        assert!(uci_options()
+            .contains("option name BOARD_TIME_DIVISOR type spin default 20 min 5 max 60"));
+        assert!(openbench().contains("ESTIMATE_TIME_BRANCHING_FACTOR, int, 8, 1, 32, 1, 0.002"));
+    }
+}
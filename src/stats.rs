@@ -1,32 +1,56 @@
+use std::cell::{Cell, RefCell};
+
 const MAX_MOVES: usize = 127;
 
-pub static mut NODES_SEARCHED: u64 = 0;
-pub static mut QNODES_SEARCHED: u64 = 0;
-pub static mut CHECK_EXTENSION: i32 = 0;
-pub static mut TT_CHECK: i32 = 0;
-pub static mut TT_HIT: i32 = 0;
-pub static mut MOVE_INDEX_DIST: [u32; MAX_MOVES] = [0; MAX_MOVES];
+// Thread local so that searches running in parallel do not count each other's nodes
+thread_local! {
+    pub static NODES_SEARCHED: Cell<u64> = const { Cell::new(0) };
+    /// Nodes of the finished iterations, `NODES_SEARCHED` only counts the current one
+    pub static PREVIOUS_NODES: Cell<u64> = const { Cell::new(0) };
+    pub static QNODES_SEARCHED: Cell<u64> = const { Cell::new(0) };
+    pub static CHECK_EXTENSION: Cell<i32> = const { Cell::new(0) };
+    pub static SINGULAR_EXTENSION: Cell<i32> = const { Cell::new(0) };
+    pub static MULTI_CUT: Cell<i32> = const { Cell::new(0) };
+    pub static TT_CHECK: Cell<i32> = const { Cell::new(0) };
+    pub static TT_HIT: Cell<i32> = const { Cell::new(0) };
+    pub static MOVE_INDEX_DIST: RefCell<[u32; MAX_MOVES]> = const { RefCell::new([0; MAX_MOVES]) };
+}
 
 #[macro_export]
 macro_rules! bump {
     ($var:expr) => {
-        unsafe {
-            $var += 1;
-        }
+        $var.set($var.get() + 1)
     };
 }
 
 pub fn reset() {
-    unsafe {
-        QNODES_SEARCHED = 0;
-        NODES_SEARCHED = 0;
-        CHECK_EXTENSION = 0;
-        TT_CHECK = 0;
-        TT_HIT = 0;
-        MOVE_INDEX_DIST = [0; MAX_MOVES];
-    };
+    QNODES_SEARCHED.set(0);
+    NODES_SEARCHED.set(0);
+    CHECK_EXTENSION.set(0);
+    SINGULAR_EXTENSION.set(0);
+    MULTI_CUT.set(0);
+    TT_CHECK.set(0);
+    TT_HIT.set(0);
+    MOVE_INDEX_DIST.set([0; MAX_MOVES]);
+}
+
+/// Starts counting the nodes of a new search
+pub fn new_search() {
+    reset();
+    PREVIOUS_NODES.set(0);
+}
+
+/// Starts a new iteration, keeping the node count of the whole search
+pub fn next_iteration() {
+    PREVIOUS_NODES.set(search_nodes());
+    reset();
+}
+
+/// Nodes of the whole search so far
+pub fn search_nodes() -> u64 {
+    PREVIOUS_NODES.get() + NODES_SEARCHED.get()
 }
 
 pub fn add_move_index(i: usize) {
-    unsafe { MOVE_INDEX_DIST[i] += 1 }
+    MOVE_INDEX_DIST.with_borrow_mut(|x| x[i] += 1);
 }
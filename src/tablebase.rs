@@ -0,0 +1,915 @@
+//! Endgame tablebases with the distance to mate, built by retrograde analysis
+//! <https://www.chessprogramming.org/Retrograde_Analysis>
+//!
+//! A table covers one material signature like `KRKP`, white's pieces first, for both sides to
+//! move. Positions are reduced by symmetry: mirrored left to right and, without pawns, also top
+//! to bottom and along the diagonal. Castling and en passant are not covered and the fifty move
+//! rule is ignored.
+//!
+//! Tables are built from the positions that are mate, and from the tables of the material left
+//! after captures and promotions, which are built first. On disk a table is a header with the
+//! signature followed by the run length encoded distances.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use arrayvec::ArrayVec;
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_rook_moves, BitBoard, ChessMove, Color,
+    Piece, Square, ALL_SQUARES, EMPTY,
+};
+
+use crate::board::Board;
+use crate::engine::OO;
+use crate::position::Position;
+
+/// Largest tables that can be generated, kings included. Five pieces would take gigabytes of
+/// memory while generating.
+pub const MAX_PIECES: u32 = 4;
+
+const MAGIC: &[u8; 4] = b"PTB1";
+const EXTENSION: &str = "ptb";
+
+/// Kings first, then from queens to pawns
+const PIECE_ORDER: [Piece; 6] = [
+    Piece::King,
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+/// Number of each piece but the kings in four bits, black's above white's. Cheap to get from
+/// a board, unlike [`Material`].
+type Key = u64;
+
+const SIDE_BITS: u32 = 20;
+
+fn shift(piece: Piece, color: Color) -> u32 {
+    color.to_index() as u32 * SIDE_BITS + 4 * piece.to_index() as u32
+}
+
+fn key_of<B: Position>(board: &B) -> Key {
+    let mut key = 0;
+    for color in [Color::White, Color::Black] {
+        for &piece in &PIECE_ORDER[1..] {
+            let count = (board.pieces(piece) & board.color_combined(color)).popcnt();
+            key |= Key::from(count) << shift(piece, color);
+        }
+    }
+    key
+}
+
+/// The key with the colors swapped
+fn swap_colors(key: Key) -> Key {
+    key >> SIDE_BITS | (key & ((1 << SIDE_BITS) - 1)) << SIDE_BITS
+}
+
+/// Kings with at most one minor piece can not mate
+fn insufficient(key: Key) -> bool {
+    key == 0
+        || [Piece::Knight, Piece::Bishop].iter().any(|&piece| {
+            [Color::White, Color::Black]
+                .iter()
+                .any(|&color| key == 1 << shift(piece, color))
+        })
+}
+
+/// Outcome for the side to move with perfect play, with the distance to mate in plies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win(u8),
+    Draw,
+    Loss(u8),
+}
+
+impl Outcome {
+    /// Search score at `ply`, mate scores like the search's own
+    #[must_use]
+    pub fn score(self, ply: u8) -> i32 {
+        match self {
+            Self::Win(n) => OO - i32::from(ply) - i32::from(n),
+            Self::Draw => 0,
+            Self::Loss(n) => -OO + i32::from(ply) + i32::from(n),
+        }
+    }
+
+    /// Outcome for the side that moved into a position with this outcome
+    /// # Panics
+    /// when the distance to mate does not fit the table format
+    fn parent(self) -> Self {
+        let next = |n: u8| n.checked_add(1).expect("distance to mate over 255 plies");
+        match self {
+            Self::Win(n) => Self::Loss(next(n)),
+            Self::Draw => Self::Draw,
+            Self::Loss(n) => Self::Win(next(n)),
+        }
+    }
+
+    /// Higher is better, quick wins and slow losses
+    fn rank(self) -> i32 {
+        match self {
+            Self::Win(n) => 512 - i32::from(n),
+            Self::Draw => 0,
+            Self::Loss(n) => -512 + i32::from(n),
+        }
+    }
+
+    /// Stored as the distance plus one, 0 is a draw. Even distances are losses.
+    fn from_value(value: u8) -> Self {
+        match value {
+            0 => Self::Draw,
+            v if (v - 1).is_multiple_of(2) => Self::Loss(v - 1),
+            v => Self::Win(v - 1),
+        }
+    }
+}
+
+/// Pieces of white and black, in [`PIECE_ORDER`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Material([Vec<Piece>; 2]);
+
+impl Material {
+    fn parse(signature: &str) -> Result<Self, String> {
+        let split = signature
+            .strip_prefix('K')
+            .and_then(|rest| rest.find('K'))
+            .ok_or_else(|| format!("`{signature}` needs a king per side, like `KRKP`"))?;
+        let (white, black) = signature.split_at(split + 1);
+
+        let pieces = |side: &str| {
+            let mut pieces = side
+                .chars()
+                .map(|c| match c {
+                    'K' => Ok(Piece::King),
+                    'Q' => Ok(Piece::Queen),
+                    'R' => Ok(Piece::Rook),
+                    'B' => Ok(Piece::Bishop),
+                    'N' => Ok(Piece::Knight),
+                    'P' => Ok(Piece::Pawn),
+                    _ => Err(format!("invalid piece `{c}` in `{signature}`")),
+                })
+                .collect::<Result<Vec<Piece>, String>>()?;
+            pieces.sort_by_key(|&piece| PIECE_ORDER.iter().position(|&x| x == piece));
+            Ok::<_, String>(pieces)
+        };
+        let material = Self([pieces(white)?, pieces(black)?]);
+
+        if material
+            .0
+            .iter()
+            .any(|side| side.iter().filter(|&&x| x == Piece::King).count() != 1)
+        {
+            return Err(format!("`{signature}` needs one king per side"));
+        }
+        Ok(material)
+    }
+
+    fn name(&self) -> String {
+        self.0
+            .iter()
+            .flatten()
+            .map(|&piece| piece.to_string(Color::White))
+            .collect()
+    }
+
+    /// Whether the table is stored with the colors swapped
+    fn flipped(&self) -> bool {
+        let strength = |side: &Vec<Piece>| {
+            let value: u32 = side
+                .iter()
+                .map(|&piece| [1, 3, 3, 5, 9, 0][piece.to_index()])
+                .sum();
+            (
+                value,
+                side.iter()
+                    .map(|piece| 5 - piece.to_index())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        strength(&self.0[1]) > strength(&self.0[0])
+    }
+
+    /// The material with the stronger side as white
+    fn canonical(&self) -> Self {
+        if self.flipped() {
+            Self([self.0[1].clone(), self.0[0].clone()])
+        } else {
+            self.clone()
+        }
+    }
+
+    fn key(&self) -> Key {
+        [Color::White, Color::Black]
+            .iter()
+            .zip(&self.0)
+            .flat_map(|(&color, side)| side.iter().map(move |&piece| (piece, color)))
+            .filter(|&(piece, _)| piece != Piece::King)
+            .map(|(piece, color)| 1 << shift(piece, color))
+            .sum()
+    }
+
+    /// Material left after a capture or a promotion
+    fn successors(&self) -> Vec<Self> {
+        let mut successors = vec![];
+        for side in 0..2 {
+            for (i, &piece) in self.0[side].iter().enumerate() {
+                if piece == Piece::King {
+                    continue;
+                }
+                let mut captured = self.clone();
+                captured.0[side].remove(i);
+                successors.push(captured);
+
+                if piece == Piece::Pawn {
+                    for promotion in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                        let mut promoted = self.clone();
+                        promoted.0[side][i] = promotion;
+                        promoted.0[side]
+                            .sort_by_key(|&piece| PIECE_ORDER.iter().position(|&x| x == piece));
+                        successors.push(promoted);
+                    }
+                }
+            }
+        }
+        successors
+    }
+}
+
+/// Squares of a transformation of the board, bit 0 mirrors files, bit 1 ranks and bit 2 swaps
+/// the two
+fn transform(square: usize, symmetry: usize) -> usize {
+    let (mut file, mut rank) = (square % 8, square / 8);
+    if symmetry & 1 != 0 {
+        file = 7 - file;
+    }
+    if symmetry & 2 != 0 {
+        rank = 7 - rank;
+    }
+    if symmetry & 4 != 0 {
+        std::mem::swap(&mut file, &mut rank);
+    }
+    8 * rank + file
+}
+
+pub struct Table {
+    material: Material,
+    /// Pieces in the order of the index, white's then black's
+    pieces: Vec<(Piece, Color)>,
+    pawns: bool,
+    /// Distance to mate in plies plus one, 0 for draws and positions that are not indexed
+    dtm: Vec<u8>,
+}
+
+impl Table {
+    fn new(material: Material, dtm: Vec<u8>) -> Self {
+        let pieces: Vec<(Piece, Color)> = material.0[0]
+            .iter()
+            .map(|&piece| (piece, Color::White))
+            .chain(material.0[1].iter().map(|&piece| (piece, Color::Black)))
+            .collect();
+        let pawns = pieces.iter().any(|&(piece, _)| piece == Piece::Pawn);
+        Self {
+            material,
+            pieces,
+            pawns,
+            dtm,
+        }
+    }
+
+    /// Squares the white king is reduced to, the left half or the a1-d1-d4 triangle
+    fn king_squares(&self) -> usize {
+        if self.pawns {
+            32
+        } else {
+            10
+        }
+    }
+
+    fn size(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    /// Index of the position, the same for all symmetric positions
+    fn index(&self, squares: &[usize], white_to_move: bool) -> usize {
+        let symmetries = if self.pawns { 2 } else { 8 };
+        let mut best = usize::MAX;
+        let mut transformed = [0; 8];
+
+        for symmetry in 0..symmetries {
+            let transformed = &mut transformed[..squares.len()];
+            for (to, &square) in transformed.iter_mut().zip(squares) {
+                *to = transform(square, symmetry);
+            }
+            // Equal pieces are interchangeable
+            let mut start = 0;
+            while start < self.pieces.len() {
+                let end = start
+                    + self.pieces[start..]
+                        .iter()
+                        .take_while(|&&x| x == self.pieces[start])
+                        .count();
+                transformed[start..end].sort_unstable();
+                start = end;
+            }
+            best = best.min(transformed.iter().fold(0, |key, &square| key * 64 + square));
+        }
+
+        let others = 64usize.pow(self.pieces.len() as u32 - 1);
+        let (king, rest) = (best / others, best % others);
+        let (file, rank) = (king % 8, king / 8);
+        let king = if self.pawns {
+            4 * rank + file
+        } else {
+            [0, 3, 5, 6][rank] + file
+        };
+
+        2 * (king * others + rest) + usize::from(!white_to_move)
+    }
+
+    /// Squares and side to move of an index
+    fn decode(&self, index: usize) -> (Vec<usize>, bool) {
+        let white_to_move = index.is_multiple_of(2);
+        let mut rest = index / 2;
+        let mut squares = vec![0; self.pieces.len()];
+        for square in squares[1..].iter_mut().rev() {
+            *square = rest % 64;
+            rest /= 64;
+        }
+        squares[0] = if self.pawns {
+            8 * (rest / 4) + rest % 4
+        } else {
+            (0..64)
+                .filter(|square| square / 8 <= square % 8 && square % 8 <= 3)
+                .nth(rest)
+                .expect("king index")
+        };
+        (squares, white_to_move)
+    }
+
+    /// Squares of the pieces of a position with this material, with the colors swapped when
+    /// `flip` is set
+    fn squares<B: Position>(
+        &self,
+        board: &B,
+        flip: bool,
+    ) -> ArrayVec<usize, { MAX_PIECES as usize }> {
+        let mut squares = ArrayVec::new();
+        let mut start = 0;
+        while start < self.pieces.len() {
+            let (piece, color) = self.pieces[start];
+            let color = if flip { !color } else { color };
+            for square in board.pieces(piece) & board.color_combined(color) {
+                squares.push(square.to_index() ^ if flip { 56 } else { 0 });
+            }
+            start += self.pieces[start..]
+                .iter()
+                .take_while(|&&x| x == self.pieces[start])
+                .count();
+        }
+        squares
+    }
+
+    fn board(&self, squares: &[usize], white_to_move: bool) -> Option<Board> {
+        let pieces: Vec<(Piece, Color, Square)> = self
+            .pieces
+            .iter()
+            .zip(squares)
+            .map(|(&(piece, color), &square)| (piece, color, ALL_SQUARES[square]))
+            .collect();
+        let side_to_move = if white_to_move {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Board::from_pieces(&pieces, side_to_move).ok()
+    }
+
+    /// Positions the side that just moved could have come from, without captures or promotions
+    fn predecessors(&self, index: usize) -> Vec<usize> {
+        let (squares, white_to_move) = self.decode(index);
+        let mover = if white_to_move {
+            Color::Black
+        } else {
+            Color::White
+        };
+        let occupied = squares.iter().fold(EMPTY, |bb, &square| {
+            bb | BitBoard::from_square(ALL_SQUARES[square])
+        });
+        let mut predecessors = vec![];
+
+        for (i, &(piece, color)) in self.pieces.iter().enumerate() {
+            if color != mover {
+                continue;
+            }
+            let square = ALL_SQUARES[squares[i]];
+            let origins = match piece {
+                Piece::Pawn => {
+                    let back = |square: Square| match mover {
+                        Color::White => square.down(),
+                        Color::Black => square.up(),
+                    };
+                    let empty =
+                        |square: &Square| occupied & BitBoard::from_square(*square) == EMPTY;
+                    let mut origins = EMPTY;
+                    // Not from the back rank
+                    if let Some(one) = back(square).filter(empty).filter(|x| back(*x).is_some()) {
+                        origins |= BitBoard::from_square(one);
+                        let double_rank = match mover {
+                            Color::White => chess::Rank::Fourth,
+                            Color::Black => chess::Rank::Fifth,
+                        };
+                        if let Some(two) = back(one)
+                            .filter(|_| square.get_rank() == double_rank)
+                            .filter(empty)
+                        {
+                            origins |= BitBoard::from_square(two);
+                        }
+                    }
+                    origins
+                }
+                Piece::Knight => get_knight_moves(square) & !occupied,
+                Piece::Bishop => get_bishop_moves(square, occupied) & !occupied,
+                Piece::Rook => get_rook_moves(square, occupied) & !occupied,
+                Piece::Queen => {
+                    (get_bishop_moves(square, occupied) | get_rook_moves(square, occupied))
+                        & !occupied
+                }
+                Piece::King => get_king_moves(square) & !occupied,
+            };
+
+            let mut previous = squares.clone();
+            for origin in origins {
+                previous[i] = origin.to_index();
+                predecessors.push(self.index(&previous, !white_to_move));
+            }
+        }
+
+        predecessors.sort_unstable();
+        predecessors.dedup();
+        predecessors
+    }
+
+    fn outcome(&self, index: usize) -> Outcome {
+        Outcome::from_value(self.dtm[index])
+    }
+
+    /// Builds the table, the tables after captures and promotions have to be in `tablebases`
+    /// # Panics
+    /// when a distance to mate does not fit the table format, which needs more than
+    /// [`MAX_PIECES`] pieces
+    fn generate(material: Material, tablebases: &Tablebases) -> Self {
+        // Position states
+        const SKIP: u8 = 0;
+        const PENDING: u8 = 1;
+        const DONE: u8 = 2;
+
+        let mut table = Self::new(material, vec![]);
+        let size = table.size();
+        table.dtm = vec![0; size];
+        let mut state = vec![SKIP; size];
+        // Distinct positions reached by moves that are not known to lose yet
+        let mut remaining = vec![0u8; size];
+        // A capture or promotion saves the game
+        let mut safe = vec![false; size];
+        // Longest loss by a capture or promotion
+        let mut longest = vec![0u8; size];
+        // Positions that are decided at a distance
+        let mut pending: Vec<Vec<u32>> = vec![vec![]; 256];
+
+        for index in 0..size {
+            let (squares, white_to_move) = table.decode(index);
+            if table.index(&squares, white_to_move) != index {
+                continue;
+            }
+            let Some(board) = table.board(&squares, white_to_move) else {
+                continue;
+            };
+            state[index] = PENDING;
+
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                if board.checkers().popcnt() > 0 {
+                    pending[0].push(index as u32);
+                } else {
+                    state[index] = DONE;
+                }
+                continue;
+            }
+
+            let mut children = vec![];
+            let mut win = None;
+            for mv in moves {
+                let next = board.make_move_new(mv);
+                if mv.get_promotion().is_none()
+                    && next.combined().popcnt() == board.combined().popcnt()
+                {
+                    children.push(table.index(&table.squares(&next, false), !white_to_move));
+                    continue;
+                }
+                match tablebases.outcome(&next).parent() {
+                    Outcome::Win(n) => win = Some(win.map_or(n, |x: u8| x.min(n))),
+                    Outcome::Draw => safe[index] = true,
+                    Outcome::Loss(n) => longest[index] = longest[index].max(n),
+                }
+            }
+            children.sort_unstable();
+            children.dedup();
+            remaining[index] = children.len() as u8;
+
+            if let Some(n) = win {
+                safe[index] = true;
+                pending[usize::from(n)].push(index as u32);
+            } else if children.is_empty() && !safe[index] {
+                pending[usize::from(longest[index])].push(index as u32);
+            }
+        }
+
+        // Odd distances win, even distances lose
+        for distance in 0..pending.len() {
+            for index in std::mem::take(&mut pending[distance]) {
+                let index = index as usize;
+                if state[index] != PENDING {
+                    continue;
+                }
+                state[index] = DONE;
+                table.dtm[index] = u8::try_from(distance + 1).unwrap_or_else(|_| {
+                    panic!("{}: distance to mate over 254 plies", table.material.name())
+                });
+
+                for previous in table.predecessors(index) {
+                    if state[previous] != PENDING {
+                        continue;
+                    }
+                    if distance.is_multiple_of(2) {
+                        pending[distance + 1].push(previous as u32);
+                    } else {
+                        remaining[previous] -= 1;
+                        if remaining[previous] == 0 && !safe[previous] {
+                            let at = (distance + 1).max(usize::from(longest[previous]));
+                            pending[at].push(previous as u32);
+                        }
+                    }
+                }
+            }
+        }
+
+        table
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let name = self.material.name();
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(name.len() as u8);
+        bytes.extend(name.bytes());
+
+        // Runs of up to 255 equal values
+        let mut values = self.dtm.iter().peekable();
+        while let Some(&value) = values.next() {
+            let mut run = 1u8;
+            while run < u8::MAX && values.next_if_eq(&&value).is_some() {
+                run += 1;
+            }
+            bytes.extend([run, value]);
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let header = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| "not a tablebase".to_string())?;
+        let (&len, rest) = header.split_first().ok_or("missing signature")?;
+        let name = rest.get(..usize::from(len)).ok_or("missing signature")?;
+        let material = Material::parse(std::str::from_utf8(name).map_err(|e| e.to_string())?)?;
+
+        if material.0.iter().map(Vec::len).sum::<usize>() > MAX_PIECES as usize {
+            return Err(format!("more than {MAX_PIECES} pieces"));
+        }
+
+        let mut table = Self::new(material, vec![]);
+        let runs = &rest[usize::from(len)..];
+        if !runs.len().is_multiple_of(2) {
+            return Err("truncated".to_string());
+        }
+        for run in runs.chunks_exact(2) {
+            table
+                .dtm
+                .extend(std::iter::repeat_n(run[1], usize::from(run[0])));
+        }
+        if table.dtm.len() != table.size() {
+            return Err(format!(
+                "expected {} positions, got {}",
+                table.size(),
+                table.dtm.len()
+            ));
+        }
+        Ok(table)
+    }
+}
+
+/// Tables by the key of their signature
+#[derive(Default)]
+pub struct Tablebases {
+    tables: HashMap<Key, Table>,
+    /// Most pieces of any table, positions with more are not probed
+    max_pieces: u32,
+}
+
+impl Tablebases {
+    /// Builds the table of a signature like `KRKP` and the ones it depends on, unless they are
+    /// already there
+    /// # Errors
+    /// when the signature is invalid
+    pub fn generate(&mut self, signature: &str) -> Result<(), String> {
+        let material = Material::parse(signature)?.canonical();
+        if material.0.iter().map(Vec::len).sum::<usize>() > MAX_PIECES as usize {
+            return Err(format!("`{signature}` has too many pieces"));
+        }
+        self.generate_material(material);
+        Ok(())
+    }
+
+    fn generate_material(&mut self, material: Material) {
+        if insufficient(material.key()) || self.tables.contains_key(&material.key()) {
+            return;
+        }
+        for successor in material.successors() {
+            self.generate_material(successor.canonical());
+        }
+        let table = Table::generate(material.clone(), self);
+        self.insert(table);
+    }
+
+    fn insert(&mut self, table: Table) {
+        self.max_pieces = self.max_pieces.max(table.pieces.len() as u32);
+        self.tables.insert(table.material.key(), table);
+    }
+
+    /// Signatures of the tables
+    #[must_use]
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .tables
+            .values()
+            .map(|table| table.material.name())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Writes every table to `<signature>.ptb` in the directory
+    /// # Errors
+    /// when a file cannot be written
+    pub fn save(&self, dir: &str) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| format!("{dir}: {e}"))?;
+        for table in self.tables.values() {
+            let path = Path::new(dir).join(format!("{}.{EXTENSION}", table.material.name()));
+            fs::write(&path, table.to_bytes()).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Reads every table in the directory
+    /// # Errors
+    /// when the directory or a table cannot be read
+    pub fn load(dir: &str) -> Result<Self, String> {
+        let mut tablebases = Self::default();
+        for entry in fs::read_dir(dir).map_err(|e| format!("{dir}: {e}"))? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().is_some_and(|x| x == EXTENSION) {
+                let bytes = fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+                let table =
+                    Table::from_bytes(&bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+                tablebases.insert(table);
+            }
+        }
+        Ok(tablebases)
+    }
+
+    /// Outcome of a position, `None` when there is no table for it. Positions with en passant or
+    /// castling possible are not probed, generation leaves them out.
+    #[must_use]
+    pub fn probe<B: Position>(&self, board: &B) -> Option<Outcome> {
+        // The search probes every node, this rules out most of them without allocating
+        if board.combined().popcnt() > self.max_pieces
+            || board.en_passant_target().is_some()
+            || [Color::White, Color::Black]
+                .iter()
+                .any(|&color| board.castle_rights(color) != chess::CastleRights::NoRights)
+        {
+            return None;
+        }
+
+        let key = key_of(board);
+        if insufficient(key) {
+            return Some(Outcome::Draw);
+        }
+        // Only the stronger side as white is stored, equal material is the same either way
+        let (table, flip) = match self.tables.get(&key) {
+            Some(table) => (table, false),
+            None => (self.tables.get(&swap_colors(key))?, true),
+        };
+        let white_to_move = (board.side_to_move() == Color::White) != flip;
+        Some(table.outcome(table.index(&table.squares(board, flip), white_to_move)))
+    }
+
+    /// Outcome while building, every table it can lead to exists
+    fn outcome(&self, board: &Board) -> Outcome {
+        if insufficient(key_of(board)) {
+            return Outcome::Draw;
+        }
+        self.probe(board).expect("table of a capture or promotion")
+    }
+
+    /// The move that keeps the best outcome, `None` unless every move can be probed
+    #[must_use]
+    pub fn best_move<B: Position>(&self, board: &B) -> Option<(ChessMove, Outcome)> {
+        self.probe(board)?;
+
+        let mut best: Option<(ChessMove, Outcome)> = None;
+        for mv in board.legal_moves() {
+            let outcome = self.probe(&board.make_move_new(mv))?.parent();
+            if best.is_none_or(|(_, x)| outcome.rank() > x.rank()) {
+                best = Some((mv, outcome));
+            }
+        }
+        best
+    }
+}
+
+/// `pica tbgen <dir> <signatures...>` builds tables like `KRKP` with the ones they depend on
+pub fn run(args: &[String]) {
+    let Some((dir, signatures)) = args.split_first().filter(|(_, x)| !x.is_empty()) else {
+        eprintln!("Usage: pica tbgen <dir> <signatures...>");
+        return;
+    };
+
+    let mut tablebases = Tablebases::default();
+    for signature in signatures {
+        let start = Instant::now();
+        if let Err(e) = tablebases.generate(signature) {
+            eprintln!("{e}");
+            return;
+        }
+        println!("{signature} in {:.1}s", start.elapsed().as_secs_f32());
+    }
+    if let Err(e) = tablebases.save(dir) {
+        eprintln!("{e}");
+        return;
+    }
+    println!("Wrote {} to {dir}", tablebases.names().join(" "));
+}
+
+mod test {
+    #[test]
+    fn test_generate() {
+        use super::*;
+        use std::str::FromStr;
+
+        let mut tablebases = Tablebases::default();
+        tablebases.generate("KPK").unwrap();
+        assert_eq!(tablebases.names(), ["KPK", "KQK", "KRK"]);
+
+        let probe = |fen| tablebases.probe(&Board::from_str(fen).unwrap());
+        // Longest mates are in 10 and 16 moves
+        let longest = |signature| {
+            let table = &tablebases.tables[&Material::parse(signature).unwrap().key()];
+            (0..table.size())
+                .filter_map(|index| match table.outcome(index) {
+                    Outcome::Win(n) => Some(n),
+                    _ => None,
+                })
+                .max()
+        };
+        assert_eq!(longest("KQK"), Some(19));
+        assert_eq!(longest("KRK"), Some(31));
+        assert_eq!(probe("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), Some(Outcome::Draw));
+        assert_eq!(
+            probe("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"),
+            Some(Outcome::Loss(0))
+        );
+        assert_eq!(
+            probe("k7/8/1K6/8/8/8/8/7R w - - 0 1"),
+            Some(Outcome::Win(1))
+        );
+        // Black has the pawn and the colors are swapped
+        assert_eq!(
+            probe("8/8/8/8/8/4k3/4p3/4K3 w - - 0 1"),
+            Some(Outcome::Draw)
+        );
+        assert!(matches!(
+            probe("8/8/8/8/8/4k3/4p3/4K3 b - - 0 1"),
+            Some(Outcome::Win(_))
+        ));
+        assert_eq!(
+            probe("8/8/4k3/8/4K3/4P3/8/8 w - - 0 1"),
+            Some(Outcome::Draw)
+        );
+        assert!(matches!(
+            probe("8/8/4k3/8/4K3/4P3/8/8 b - - 0 1"),
+            Some(Outcome::Loss(_))
+        ));
+        // Keys of boards and signatures agree, also with the colors swapped
+        let board = Board::from_str("8/8/8/8/8/4k3/4p3/4K3 w - - 0 1").unwrap();
+        let kpk = Material::parse("KPK").unwrap().key();
+        assert_eq!(swap_colors(key_of(&board)), kpk);
+        assert_eq!(swap_colors(kpk), Material::parse("KKP").unwrap().key());
+        assert!(insufficient(Material::parse("KKB").unwrap().key()));
+        assert!(!insufficient(Material::parse("KNKN").unwrap().key()));
+        // Nothing covers these
+        assert_eq!(probe("8/8/4k3/8/4K3/4P3/8/7R w - - 0 1"), None);
+        assert_eq!(probe("4k3/8/8/8/8/8/8/4K2R w K - 0 1"), None);
+        assert_eq!(probe("4k3/8/8/8/8/8/8/4K2N w - - 0 1"), Some(Outcome::Draw));
+
+        // Every KPK result agrees with the bitbase
+        for (index, &value) in tablebases.tables[&Material::parse("KPK").unwrap().key()]
+            .dtm
+            .iter()
+            .enumerate()
+            .step_by(7)
+        {
+            let table = &tablebases.tables[&Material::parse("KPK").unwrap().key()];
+            let (squares, white_to_move) = table.decode(index);
+            if table.index(&squares, white_to_move) != index
+                || table.board(&squares, white_to_move).is_none()
+            {
+                continue;
+            }
+            let [king, pawn, other] = squares[..] else {
+                unreachable!()
+            };
+            let win = crate::endgame::kpk_win(
+                Color::White,
+                ALL_SQUARES[king],
+                ALL_SQUARES[pawn],
+                ALL_SQUARES[other],
+                white_to_move,
+            );
+            let outcome = Outcome::from_value(value);
+            let expected = if white_to_move {
+                outcome.rank() > 0
+            } else {
+                outcome.rank() < 0
+            };
+            assert_eq!(win, expected, "{squares:?} {white_to_move}");
+        }
+
+        let board = Board::from_str("k7/8/1K6/8/8/8/8/7R w - - 0 1").unwrap();
+        let (mv, outcome) = tablebases.best_move(&board).unwrap();
+        assert_eq!(mv, ChessMove::from_str("h1h8").unwrap());
+        assert_eq!(outcome, Outcome::Win(1));
+        assert_eq!(outcome.score(3), OO - 4);
+
+        // Saved and loaded tables are the same
+        let dir = std::env::temp_dir().join(format!("pica-tb-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        tablebases.save(dir).unwrap();
+        let loaded = Tablebases::load(dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(loaded.names(), tablebases.names());
+        for (key, table) in &tablebases.tables {
+            assert_eq!(
+                loaded.tables[key].dtm,
+                table.dtm,
+                "{}",
+                table.material.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        use super::*;
+        use crate::engine::Engine;
+        use crate::time::TimeManager;
+        use crate::utils::{is_mate_score, History};
+        use std::str::FromStr;
+        use std::sync::Arc;
+
+        let mut tablebases = Tablebases::default();
+        tablebases.generate("KQK").unwrap();
+        let mut engine = Engine::new(1);
+        engine.tablebases = Some(Arc::new(tablebases));
+        let time = TimeManager {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+
+        // The root plays the table's move
+        let board = Board::from_str("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+        let (mv, score) = engine.search(board, &time, History::new());
+        assert_eq!(mv, ChessMove::from_str("b1b8").unwrap());
+        assert_eq!(score, OO - 1);
+
+        // There is no table for the position itself, but after taking the rook the search knows
+        // the mate that is too far to see
+        let board = Board::from_str("6k1/8/8/8/8/8/r7/QK6 w - - 0 1").unwrap();
+        let (mv, score) = engine.search(board, &time, History::new());
+        assert_eq!(mv.get_dest(), Square::A2);
+        assert!(is_mate_score(score), "{score}");
+    }
+}
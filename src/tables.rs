@@ -2,14 +2,14 @@ use chess::{BitBoard, File, Rank, Square};
 use const_for::const_for;
 use lazy_static::lazy_static;
 
-const MG_VALUE: [i32; 6] = [82, 337, 365, 477, 1025, 0];
-const EG_VALUE: [i32; 6] = [94, 281, 297, 512, 936, 0];
+pub const MG_VALUE: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+pub const EG_VALUE: [i32; 6] = [94, 281, 297, 512, 936, 0];
 
 /* piece/sq tables */
 /* values from Rofchade: http://www.talkchess.com/forum3/viewtopic.php?f=2&t=68311&start=19 */
 
 #[rustfmt::skip]
-const MG_PAWN_TABLE: [i32; 64] = [
+pub const MG_PAWN_TABLE: [i32; 64] = [
     0,   0,   0,   0,   0,   0,  0,   0,
     98, 134,  61,  95,  68, 126, 34, -11,
     -6,   7,  26,  31,  65,  56, 25, -20,
@@ -21,7 +21,7 @@ const MG_PAWN_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const EG_PAWN_TABLE: [i32; 64] = [
+pub const EG_PAWN_TABLE: [i32; 64] = [
     0,   0,   0,   0,   0,   0,   0,   0,
     178, 173, 158, 134, 147, 132, 165, 187,
      94, 100,  85,  67,  56,  53,  82,  84,
@@ -33,7 +33,7 @@ const EG_PAWN_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const MG_KNIGHT_TABLE: [i32; 64] = [
+pub const MG_KNIGHT_TABLE: [i32; 64] = [
     -167, -89, -34, -49,  61, -97, -15, -107,
      -73, -41,  72,  36,  23,  62,   7,  -17,
      -47,  60,  37,  65,  84, 129,  73,   44,
@@ -45,7 +45,7 @@ const MG_KNIGHT_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const EG_KNIGHT_TABLE: [i32; 64] = [
+pub const EG_KNIGHT_TABLE: [i32; 64] = [
     -58, -38, -13, -28, -31, -27, -63, -99,
     -25,  -8, -25,  -2,  -9, -25, -24, -52,
     -24, -20,  10,   9,  -1,  -9, -19, -41,
@@ -57,7 +57,7 @@ const EG_KNIGHT_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const MG_BISHOP_TABLE: [i32; 64] = [
+pub const MG_BISHOP_TABLE: [i32; 64] = [
     -29,   4, -82, -37, -25, -42,   7,  -8,
     -26,  16, -18, -13,  30,  59,  18, -47,
     -16,  37,  43,  40,  35,  50,  37,  -2,
@@ -69,7 +69,7 @@ const MG_BISHOP_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const EG_BISHOP_TABLE: [i32; 64] = [
+pub const EG_BISHOP_TABLE: [i32; 64] = [
     -14, -21, -11,  -8, -7,  -9, -17, -24,
      -8,  -4,   7, -12, -3, -13,  -4, -14,
       2,  -8,   0,  -1, -2,   6,   0,   4,
@@ -81,7 +81,7 @@ const EG_BISHOP_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const MG_ROOK_TABLE: [i32; 64] = [
+pub const MG_ROOK_TABLE: [i32; 64] = [
     32,  42,  32,  51, 63,  9,  31,  43,
     27,  32,  58,  62, 80, 67,  26,  44,
     -5,  19,  26,  36, 17, 45,  61,  16,
@@ -93,7 +93,7 @@ const MG_ROOK_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const EG_ROOK_TABLE: [i32; 64] = [
+pub const EG_ROOK_TABLE: [i32; 64] = [
     13, 10, 18, 15, 12,  12,   8,   5,
     11, 13, 13, 11, -3,   3,   8,   3,
      7,  7,  7,  5,  4,  -3,  -5,  -3,
@@ -105,7 +105,7 @@ const EG_ROOK_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const MG_QUEEN_TABLE: [i32; 64] = [
+pub const MG_QUEEN_TABLE: [i32; 64] = [
     -28,   0,  29,  12,  59,  44,  43,  45,
     -24, -39,  -5,   1, -16,  57,  28,  54,
     -13, -17,   7,   8,  29,  56,  47,  57,
@@ -117,7 +117,7 @@ const MG_QUEEN_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const EG_QUEEN_TABLE: [i32; 64] = [
+pub const EG_QUEEN_TABLE: [i32; 64] = [
     -9,  22,  22,  27,  27,  19,  10,  20,
     -17,  20,  32,  41,  58,  25,  30,   0,
     -20,   6,   9,  49,  47,  35,  19,   9,
@@ -129,7 +129,7 @@ const EG_QUEEN_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const MG_KING_TABLE: [i32; 64] = [
+pub const MG_KING_TABLE: [i32; 64] = [
     -65,  23,  16, -15, -56, -34,   2,  13,
      29,  -1, -20,  -7,  -8,  -4, -38, -29,
      -9,  24,   2, -16, -20,   6,  22, -22,
@@ -141,7 +141,7 @@ const MG_KING_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-const EG_KING_TABLE: [i32; 64] = [
+pub const EG_KING_TABLE: [i32; 64] = [
     -74, -35, -18, -18, -11,  15,   4, -17,
     -12,  17,  14,  17,  17,  38,  23,  11,
      10,  17,  23,  15,  20,  45,  44,  13,
@@ -164,45 +164,65 @@ const EG_KING_TABLE: [i32; 64] = [
 //      0,  1,  2,  3,  4,  5,  6,  7,
 // ];
 
-const MG_PIECES: [[i32; 64]; 6] = [
-    MG_PAWN_TABLE,
-    MG_KNIGHT_TABLE,
-    MG_BISHOP_TABLE,
-    MG_ROOK_TABLE,
-    MG_QUEEN_TABLE,
-    MG_KING_TABLE,
-];
-const EG_PIECES: [[i32; 64]; 6] = [
-    EG_PAWN_TABLE,
-    EG_KNIGHT_TABLE,
-    EG_BISHOP_TABLE,
-    EG_ROOK_TABLE,
-    EG_QUEEN_TABLE,
-    EG_KING_TABLE,
-];
+/* mobility tables, indexed by the number of reachable squares */
+/* derived from Stockfish's classical mobility bonus, halved and centred around typical mobility */
 
-pub const MG: [[i32; 64]; 6] = {
-    let mut mg = [[0; 64]; 6];
+pub const MG_KNIGHT_MOBILITY: [i32; 9] = [-30, -25, -4, 0, 3, 8, 12, 16, 20];
+pub const EG_KNIGHT_MOBILITY: [i32; 9] = [-31, -20, -7, 0, 12, 15, 16, 19, 22];
 
-    const_for!(piece in 0..6 => {
-        const_for!(square in 0..64 => {
-            mg[piece][square] = MG_PIECES[piece][square] + MG_VALUE[piece];
-        });
-    });
+#[rustfmt::skip]
+pub const MG_BISHOP_MOBILITY: [i32; 14] = [-43, -30, -12, -5, 0, 7, 7, 10, 12, 15, 20, 22, 26, 28];
+#[rustfmt::skip]
+pub const EG_BISHOP_MOBILITY: [i32; 14] = [-40, -23, -14, -4, 0, 10, 18, 18, 22, 26, 28, 33, 34, 38];
 
-    mg
-};
+#[rustfmt::skip]
+pub const MG_ROOK_MOBILITY: [i32; 15] = [-37, -19, -7, -6, -5, 0, 3, 8, 14, 14, 14, 16, 22, 22, 26];
+#[rustfmt::skip]
+pub const EG_ROOK_MOBILITY: [i32; 15] = [-91, -58, -42, -28, -14, 0, 1, 11, 16, 20, 26, 30, 32, 35, 38];
+
+#[rustfmt::skip]
+pub const MG_QUEEN_MOBILITY: [i32; 28] = [
+    -42, -35, -31, -31, -18, -14, -16, -8, -6, 0, 6, 7, 8, 8,
+      8,   8,   8,   9,  10,  11,  18, 25, 26, 26, 29, 30, 30, 32,
+];
+#[rustfmt::skip]
+pub const EG_QUEEN_MOBILITY: [i32; 28] = [
+    -72, -62, -52, -39, -28, -20, -18, -11, -10, 0, 0, 3, 14, 16,
+     18,  19,  20,  22,  26,  27,  29,  37,  38, 38, 42, 45, 46, 63,
+];
+
+/// Nonlinear king safety penalty, indexed by the attack units on the king zone
+/// <https://www.chessprogramming.org/King_Safety#Attack_Units>
+#[rustfmt::skip]
+pub const KING_SAFETY_TABLE: [i32; 100] = [
+      0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+     18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+     68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
 
-pub const EG: [[i32; 64]; 6] = {
-    let mut eg = [[0; 64]; 6];
+/// Zobrist keys for pawns only, indexed by color and square.
+/// Generated with xorshift64 from a fixed seed so the keys are stable between builds.
+pub const PAWN_ZOBRIST: [[u64; 64]; 2] = {
+    let mut keys = [[0; 64]; 2];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
 
-    const_for!(piece in 0..6 => {
+    const_for!(color in 0..2 => {
         const_for!(square in 0..64 => {
-            eg[piece][square] = EG_PIECES[piece][square] + EG_VALUE[piece];
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            keys[color][square] = state;
         });
     });
 
-    eg
+    keys
 };
 
 lazy_static! {
@@ -14,7 +14,7 @@ mod test {
     impl Arena {
         pub fn new(fen: &str, winningside: Color) -> Self {
             Self {
-                board: Board::from_str(&fen).unwrap(),
+                board: Board::from_str(fen).unwrap(),
                 eng: Engine::new(128),
                 winningside,
             }
@@ -57,17 +57,27 @@ mod test {
 
     macro_rules! nextmoveassert_san {
         ($fen:expr, $move:expr) => {
+            use crate::params::EvalParams;
+            use crate::pawn_table::PawnTable;
             use crate::tt::TT;
             use chess::ChessMove;
             let board = Board::from_str($fen.trim()).unwrap();
             let mv = Engine {
                 tt: TT::new_with_size_mb(256),
+                pawns: PawnTable::new(),
+                params: EvalParams::default(),
+                nnue: None,
+                tablebases: None,
                 info: true,
+                chess960: false,
+                xboard: false,
             }
             .start(board, &TimeManager::test_preset(), History::new());
-            let bestmv = ChessMove::from_san(&board, $move).unwrap();
+            // SAN is read by the `chess` crate
+            let chess_board = chess::Board::from_str($fen.trim()).unwrap();
+            let bestmv = ChessMove::from_san(&chess_board, $move).unwrap();
             assert_eq!(
-                mv.to_string(),
+                board.format_move(mv, false),
                 bestmv.to_string(),
                 "{}",
                 format!("FEN: {}", board)
@@ -78,18 +88,24 @@ mod test {
 
     macro_rules! nextmoveassert_uci {
         ($fen:expr, $move:expr) => {
+            use crate::params::EvalParams;
+            use crate::pawn_table::PawnTable;
             use crate::tt::TT;
-            use chess::ChessMove;
             let board = Board::from_str($fen.trim()).unwrap();
             let mv = Engine {
                 tt: TT::new_with_size_mb(256),
+                pawns: PawnTable::new(),
+                params: EvalParams::default(),
+                nnue: None,
+                tablebases: None,
                 info: true,
+                chess960: false,
+                xboard: false,
             }
             .start(board, &TimeManager::test_preset(), History::new());
-            let bestmv = ChessMove::from_str($move).unwrap();
             assert_eq!(
-                mv.to_string(),
-                bestmv.to_string(),
+                board.format_move(mv, false),
+                $move,
                 "{}",
                 format!("FEN: {}", board)
             );
@@ -155,7 +171,7 @@ mod test {
 
         let positions = "3k4/8/4K3/2R5/8/8/8/8 w - - bm Rc1
     4k3/8/4K3/8/8/8/2R5/8 w - - 2 2 bm Rc8
-    1k6/7R/2K5/8/8/8/8/8 w - - bm Rh1
+    1k6/7R/2K5/8/8/8/8/8 w - - bm Rh8
     8/3k4/8/8/3PK3/8/8/8 w - - bm Kd5
     2k5/8/1K1P4/8/8/8/8/8 w - - bm Kc6"
             .lines();
@@ -1,9 +1,15 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
 
-use chess::Board;
 use vampirc_uci::UciTimeControl;
 
-use crate::{engine::MAX_PLY, stats::NODES_SEARCHED};
+use crate::{
+    engine::MAX_PLY,
+    position::Position,
+    spsa::{BOARD_TIME_DIVISOR, ESTIMATE_TIME_BRANCHING_FACTOR},
+    stats,
+};
 
 #[derive(Debug, Default)]
 pub struct TimeManager {
@@ -11,19 +17,23 @@ pub struct TimeManager {
     pub min_depth: Option<u8>,
     pub max_nodes: Option<u64>,
     pub board_time: Option<u32>,
+    /// Added to the clock after every move, in ms
+    pub increment: Option<u32>,
+    /// Moves until the clock is refilled
+    pub moves_to_go: Option<u32>,
     pub max_allowed_time_now: Option<u32>,
+    /// Stops the search once set, from another thread
+    pub stop: Option<Arc<AtomicBool>>,
 }
 
-const ESTIMATE_TIME_BRANCHING_FACTOR: u32 = 8;
-
 impl TimeManager {
     // https://www.chessprogramming.org/Time_Management
     #[must_use]
-    pub fn can_continue_soft(
+    pub fn can_continue_soft<B: Position>(
         &self,
         depth: u8,
-        _board: Board,
-        _nodes: u64,
+        _board: B,
+        nodes: u64,
         start_of_search: Instant,
     ) -> bool {
         // Check for minimum depth
@@ -37,13 +47,21 @@ impl TimeManager {
             return false;
         }
 
+        // Nodes of the whole search, not just the last iteration
+        if nodes > self.max_nodes.unwrap_or(u64::MAX) {
+            return false;
+        }
+
+        if self.stopped() {
+            return false;
+        }
+
         let time_ms = Instant::now();
         let ms = time_ms.duration_since(start_of_search).as_millis() as u32
-            * ESTIMATE_TIME_BRANCHING_FACTOR;
-        let board_time = self.board_time.unwrap_or(300_000);
+            * ESTIMATE_TIME_BRANCHING_FACTOR.get() as u32;
 
         // Normal board time
-        if ms > board_time / 20 {
+        if ms > self.move_budget() {
             return false;
         }
 
@@ -58,26 +76,34 @@ impl TimeManager {
         true
     }
 
-    pub fn can_continue_hard(&self, depth: u8, _board: &Board, start_of_search: Instant) -> bool {
+    pub fn can_continue_hard<B: Position>(
+        &self,
+        depth: u8,
+        _board: &B,
+        start_of_search: Instant,
+    ) -> bool {
         // Check for max depth
         if depth > self.max_depth.unwrap_or(MAX_PLY) {
             // println!("fail hard max depth {}>{}", depth, self.max_depth.unwrap_or(MAX_PLY));
             return false;
         }
 
+        if self.stopped() {
+            return false;
+        }
+
         let time_ms = Instant::now();
         let ms = time_ms.duration_since(start_of_search).as_millis() as u32;
-        let board_time: u32 = self.board_time.unwrap_or(300_000);
 
         // Normal board time
-        if ms > board_time / 20 {
+        if ms > self.move_budget() {
             // println!("fail hard board time {} > {} / 20 ({})", ms, board_time, board_time / 20);
             return false;
         }
 
-        // Nodes
-        if unsafe { NODES_SEARCHED } > self.max_nodes.unwrap_or(u64::MAX) {
-            // println!("fail hard nodes {} > {}", unsafe { NODES_SEARCHED }, self.max_nodes.unwrap_or(u64::MAX));
+        // Nodes of the whole search, not just the current iteration
+        if stats::search_nodes() > self.max_nodes.unwrap_or(u64::MAX) {
+            // println!("fail hard nodes {} > {}", stats::search_nodes(), self.max_nodes.unwrap_or(u64::MAX));
             return false;
         }
 
@@ -92,8 +118,22 @@ impl TimeManager {
         true
     }
 
+    fn stopped(&self) -> bool {
+        self.stop.as_ref().is_some_and(|x| x.load(Ordering::SeqCst))
+    }
+
+    /// Time for this move in ms: a share of the clock, a bigger one when the clock is refilled
+    /// soon, plus most of the increment. Never more than half the clock.
+    fn move_budget(&self) -> u32 {
+        let board_time = self.board_time.unwrap_or(300_000);
+        let divisor = BOARD_TIME_DIVISOR.get() as u32;
+        let moves = self.moves_to_go.map_or(divisor, |x| (x + 1).min(divisor));
+
+        (board_time / moves + self.increment.unwrap_or(0) * 3 / 4).min(board_time / 2)
+    }
+
     #[must_use]
-    pub fn from_uci(uci: &UciTimeControl, board: &Board) -> Self {
+    pub fn from_uci<B: Position>(uci: &UciTimeControl, board: &B) -> Self {
         match uci {
             UciTimeControl::Infinite => Self {
                 ..Default::default()
@@ -124,6 +164,7 @@ impl TimeManager {
     }
 
     #[must_use]
+    #[allow(dead_code)] // Only used by the binary's tests
     pub fn test_preset() -> Self {
         Self {
             max_allowed_time_now: Some(5000),
@@ -0,0 +1,344 @@
+//! Texel tuning of the evaluation parameters
+//! <https://www.chessprogramming.org/Texel%27s_Tuning_Method>
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::str::FromStr;
+use std::thread;
+
+use crate::board::Board;
+use crate::eval::{eval_coefficients, Coefficients};
+use crate::params::{EvalParams, NUM_PARAMS, PARAM_GROUPS};
+
+/// Adam hyperparameters
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+/// A quiet position with the result of its game from white's point of view
+pub struct TuningPosition {
+    pub coefficients: Coefficients,
+    pub result: f64,
+}
+
+/// Parses a game result, either as `1-0`, `0-1`, `1/2-1/2` or as a number
+fn parse_result(result: &str) -> Option<f64> {
+    match result
+        .trim()
+        .trim_matches(|c| c == '"' || c == '[' || c == ']' || c == ';')
+    {
+        "1-0" => Some(1.),
+        "0-1" => Some(0.),
+        "1/2-1/2" => Some(0.5),
+        x => x.parse().ok().filter(|x| (0. ..=1.).contains(x)),
+    }
+}
+
+/// Parses one line of a tuning file.
+/// Accepted formats are `FEN | score | result`, `FEN [result]`, EPD with `c9 "result";`
+/// and `FEN result`.
+#[must_use]
+pub fn parse_line(line: &str) -> Option<(Board, f64)> {
+    let line = line.trim();
+
+    let (fen, result) = if line.contains('|') {
+        let fields: Vec<&str> = line.split('|').collect();
+        (fields[0], *fields.last()?)
+    } else if let Some((fen, result)) = line.split_once(" c9 ") {
+        (fen, result)
+    } else if let Some((fen, result)) = line.split_once('[') {
+        (fen, result)
+    } else {
+        line.rsplit_once(char::is_whitespace)?
+    };
+
+    let fen = fen.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let board = Board::from_str(&fen).ok()?;
+
+    Some((board, parse_result(result)?))
+}
+
+/// Loads a file of positions, skipping lines that cannot be parsed
+/// # Errors
+/// when the file cannot be read
+pub fn load_positions(path: &str) -> io::Result<Vec<TuningPosition>> {
+    let mut positions = vec![];
+    let mut skipped = 0;
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        match parse_line(&line?) {
+            Some((board, result)) => positions.push(TuningPosition {
+                coefficients: eval_coefficients(&board),
+                result,
+            }),
+            None => skipped += 1,
+        }
+    }
+
+    if skipped > 0 {
+        eprintln!("> Skipped {skipped} unparsable lines");
+    }
+
+    Ok(positions)
+}
+
+fn linear_eval(coefficients: &Coefficients, params: &[f64]) -> f64 {
+    f64::from(coefficients.constant)
+        + coefficients
+            .weights
+            .iter()
+            .map(|&(i, w)| f64::from(w) * params[usize::from(i)])
+            .sum::<f64>()
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1. / (1. + 10f64.powf(-k * score / 400.))
+}
+
+/// Splits the positions over all cores and sums up the results of `f`
+fn parallel_sum<T: Send>(
+    positions: &[TuningPosition],
+    init: impl Fn() -> T + Sync,
+    f: impl Fn(&mut T, &TuningPosition) + Sync,
+    merge: impl Fn(&mut T, T),
+) -> T {
+    let threads = thread::available_parallelism().map_or(1, std::num::NonZero::get);
+    let chunk_size = positions.len().div_ceil(threads).max(1);
+
+    let (init, f) = (&init, &f);
+    thread::scope(|s| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    let mut acc = init();
+                    for position in chunk {
+                        f(&mut acc, position);
+                    }
+                    acc
+                })
+            })
+            .collect();
+
+        let mut total = init();
+        for handle in handles {
+            merge(&mut total, handle.join().expect("tuning thread panicked"));
+        }
+        total
+    })
+}
+
+/// Mean squared error between the game results and the predicted results
+#[must_use]
+pub fn mean_error(positions: &[TuningPosition], params: &[f64], k: f64) -> f64 {
+    let total = parallel_sum(
+        positions,
+        || 0.,
+        |acc, p| {
+            let error = p.result - sigmoid(linear_eval(&p.coefficients, params), k);
+            *acc += error * error;
+        },
+        |acc, x| *acc += x,
+    );
+
+    total / positions.len() as f64
+}
+
+/// Finds the scaling constant K which minimizes the error of the given parameters
+#[must_use]
+pub fn fit_k(positions: &[TuningPosition], params: &[f64]) -> f64 {
+    // Golden section search
+    let ratio = (5f64.sqrt() - 1.) / 2.;
+    let (mut low, mut high) = (0., 5.);
+
+    for _ in 0..50 {
+        let a = high - ratio * (high - low);
+        let b = low + ratio * (high - low);
+
+        if mean_error(positions, params, a) < mean_error(positions, params, b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+
+    (low + high) / 2.
+}
+
+fn gradient(positions: &[TuningPosition], params: &[f64], k: f64) -> Vec<f64> {
+    let mut gradient = parallel_sum(
+        positions,
+        || vec![0.; NUM_PARAMS],
+        |acc, p| {
+            let s = sigmoid(linear_eval(&p.coefficients, params), k);
+            // Derivative of the squared error with respect to the evaluation
+            let d = -2. * (p.result - s) * s * (1. - s) * k * 10f64.ln() / 400.;
+
+            for &(i, w) in &p.coefficients.weights {
+                acc[usize::from(i)] += d * f64::from(w);
+            }
+        },
+        |acc, x| acc.iter_mut().zip(x).for_each(|(a, b)| *a += b),
+    );
+
+    let n = positions.len() as f64;
+    gradient.iter_mut().for_each(|x| *x /= n);
+    gradient
+}
+
+/// Runs Adam over the parameters for the given number of epochs
+#[must_use]
+pub fn tune(
+    positions: &[TuningPosition],
+    params: &EvalParams,
+    k: f64,
+    epochs: usize,
+    verbose: bool,
+) -> EvalParams {
+    let mut weights: Vec<f64> = params.values.iter().map(|&x| f64::from(x)).collect();
+    let mut m = vec![0.; NUM_PARAMS];
+    let mut v = vec![0.; NUM_PARAMS];
+
+    for epoch in 1..=epochs {
+        let gradient = gradient(positions, &weights, k);
+
+        for i in 0..NUM_PARAMS {
+            m[i] = BETA1 * m[i] + (1. - BETA1) * gradient[i];
+            v[i] = BETA2 * v[i] + (1. - BETA2) * gradient[i] * gradient[i];
+
+            let m_hat = m[i] / (1. - BETA1.powi(epoch as i32));
+            let v_hat = v[i] / (1. - BETA2.powi(epoch as i32));
+            weights[i] -= LEARNING_RATE * m_hat / (v_hat.sqrt() + EPSILON);
+        }
+
+        if verbose && (epoch % 50 == 0 || epoch == epochs) {
+            println!(
+                "epoch {epoch} error {:.6}",
+                mean_error(positions, &weights, k)
+            );
+        }
+    }
+
+    let mut tuned = params.clone();
+    for (value, weight) in tuned.values.iter_mut().zip(weights) {
+        *value = weight.round() as i32;
+    }
+    tuned
+}
+
+/// Writes the parameters as Rust constants, in the same layout as `tables.rs` and `eval.rs`
+/// # Errors
+/// when writing fails
+pub fn write_rust(params: &EvalParams, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "// Generated by `pica tune`")?;
+
+    for group in PARAM_GROUPS {
+        let values = &params.values[group.offset..group.offset + group.len];
+
+        if group.len == 1 {
+            writeln!(out, "\npub const {}: i32 = {};", group.name, values[0])?;
+            continue;
+        }
+
+        writeln!(out, "\n#[rustfmt::skip]")?;
+        writeln!(out, "pub const {}: [i32; {}] = [", group.name, group.len)?;
+        for row in values.chunks(if group.len == 64 { 8 } else { 10 }) {
+            let row: Vec<String> = row.iter().map(|x| format!("{x:4},")).collect();
+            writeln!(out, "   {}", row.join(""))?;
+        }
+        writeln!(out, "];")?;
+    }
+
+    Ok(())
+}
+
+/// Entry point of `pica tune <positions> [epochs] [output]`
+pub fn run(args: &[String]) {
+    let Some(path) = args.first() else {
+        eprintln!("Usage: pica tune <positions> [epochs] [output]");
+        return;
+    };
+    let epochs = args
+        .get(1)
+        .map_or(1000, |x| x.parse().expect("parse epochs"));
+    let output = args.get(2).map_or("tuned.rs", String::as_str);
+
+    let positions = load_positions(path).expect("load positions");
+    println!("Loaded {} positions", positions.len());
+    if positions.is_empty() {
+        return;
+    }
+
+    let params = EvalParams::default();
+    let weights: Vec<f64> = params.values.iter().map(|&x| f64::from(x)).collect();
+    let k = fit_k(&positions, &weights);
+    println!("K {k:.4} error {:.6}", mean_error(&positions, &weights, k));
+
+    let tuned = tune(&positions, &params, k, epochs, true);
+
+    let mut file = File::create(output).expect("create output file");
+    write_rust(&tuned, &mut file).expect("write output file");
+    println!("Wrote {output}");
+}
+
+mod test {
+    #[test]
+    fn test_parse_line() {
+        use super::parse_line;
+        use crate::board::Board;
+
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        for (line, result) in [
+            (format!("{fen} | 35 | 1.0"), 1.),
+            (format!("{fen} [0.5]"), 0.5),
+            (format!("{fen} [1-0]"), 1.),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - c9 \"0-1\";".to_string(),
+                0.,
+            ),
+            (format!("{fen} 1/2-1/2"), 0.5),
+        ] {
+            let (board, r) = parse_line(&line).unwrap();
+            assert_eq!(board, Board::default(), "{line}");
+            assert!((r - result).abs() < f64::EPSILON, "{line}");
+        }
+
+        assert!(parse_line("garbage").is_none());
+    }
+
+    #[test]
+    fn test_tune() {
+        use super::*;
+
+        // White is always winning these, so tuning has to push the error down.
+        // The h-pawns keep them out of the endgames with their own evaluation.
+        let positions: Vec<TuningPosition> = [
+            "4k3/7p/8/8/8/8/4P2P/4K3 w - - 0 1",
+            "4k3/7p/8/8/8/4P3/7P/4K3 b - - 0 1",
+            "4k3/7p/8/8/3P4/8/7P/4K3 w - - 0 1",
+            "4k3/7p/8/8/8/8/3PP2P/4K3 w - - 0 1",
+        ]
+        .iter()
+        .map(|fen| TuningPosition {
+            coefficients: eval_coefficients(&Board::from_str(fen).unwrap()),
+            result: 1.,
+        })
+        .collect();
+
+        let params = EvalParams::default();
+        let weights: Vec<f64> = params.values.iter().map(|&x| f64::from(x)).collect();
+        let k = fit_k(&positions, &weights);
+        let before = mean_error(&positions, &weights, k);
+
+        let tuned = tune(&positions, &params, k, 20, false);
+        let weights: Vec<f64> = tuned.values.iter().map(|&x| f64::from(x)).collect();
+        assert!(mean_error(&positions, &weights, k) < before);
+
+        let mut out = vec![];
+        write_rust(&tuned, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("pub const MG_PAWN_TABLE: [i32; 64] = ["));
+        assert!(out.contains("pub const TEMPO_BONUS: i32 = "));
+    }
+}
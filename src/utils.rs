@@ -1,19 +1,46 @@
+use std::cmp::Reverse;
 use std::time::Instant;
 
 use arrayvec::ArrayVec;
 
-use chess::{BitBoard, Board, BoardStatus, ChessMove, MoveGen, Piece};
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_pawn_moves, get_rank,
+    get_rook_moves, line, BitBoard, ChessMove, Color, Piece, Square,
+};
 
 use crate::{
+    board::MoveList,
     engine::{MAX_PLY, OO},
-    stats::{CHECK_EXTENSION, NODES_SEARCHED, QNODES_SEARCHED, TT_CHECK, TT_HIT},
+    position::Position,
+    spsa::HISTORY_ORDERING,
+    stats::{
+        CHECK_EXTENSION, MULTI_CUT, NODES_SEARCHED, QNODES_SEARCHED, SINGULAR_EXTENSION, TT_CHECK,
+        TT_HIT,
+    },
     tt::TT,
 };
 
+/// Bound of the history tables
+const MAX_HISTORY: i32 = 16384;
+/// Move ordering score of the countermove, above all other quiets
+const COUNTERMOVE_VALUE: i32 = i32::MAX;
+/// Queen promotions are ordered like a pawn capturing a queen
+const PROMOTION_VALUE: i32 = MVV_LVA[5][1] as i32;
+
 #[derive(Debug)]
 pub struct SearchInfo {
     pub killers: [[Option<ChessMove>; MAX_PLY as usize + 1]; 2],
-    pub history: [[u32; 64]; 64],
+    /// Butterfly history, indexed by side to move, source and destination
+    pub history: [[[i32; 64]; 64]; 2],
+    /// Refutation of the previous move, indexed by its piece and destination
+    pub countermoves: [[Option<ChessMove>; 64]; 12],
+    /// Continuation history, indexed by piece and destination of an earlier move,
+    /// then by piece and destination of the current one
+    pub continuation: Vec<[[i16; 64]; 12]>,
+    /// Piece and destination of the move played at each ply
+    pub stack: [Option<(usize, Square)>; MAX_PLY as usize + 1],
+    /// Move skipped by the singular extension search at each ply
+    pub excluded: [Option<ChessMove>; MAX_PLY as usize + 1],
     pub start: Instant,
 }
 
@@ -28,17 +55,108 @@ impl SearchInfo {
     pub fn new(start: Instant) -> Self {
         Self {
             killers: [[None; MAX_PLY as usize + 1]; 2],
-            history: [[0; 64]; 64],
+            history: [[[0; 64]; 64]; 2],
+            countermoves: [[None; 64]; 12],
+            continuation: vec![[[0; 64]; 12]; 12 * 64],
+            stack: [None; MAX_PLY as usize + 1],
+            excluded: [None; MAX_PLY as usize + 1],
             start,
         }
     }
+
+    /// Move played `back` plies before `ply`
+    fn previous(&self, ply: u8, back: u8) -> Option<(usize, Square)> {
+        self.stack[ply.checked_sub(back)? as usize]
+    }
+
+    /// Countermove of the move that led to `ply`
+    #[must_use]
+    pub fn countermove(&self, ply: u8) -> Option<ChessMove> {
+        let (piece, square) = self.previous(ply, 1)?;
+        self.countermoves[piece][square.to_index()]
+    }
+
+    /// Combined butterfly and 1/2 ply continuation history of a quiet move
+    #[must_use]
+    pub fn quiet_history<B: Position>(&self, board: &B, mv: ChessMove, ply: u8) -> i32 {
+        let piece = piece_index(board, mv);
+        let to = mv.get_dest().to_index();
+
+        let mut score =
+            self.history[board.side_to_move().to_index()][mv.get_source().to_index()][to];
+        for back in [1, 2] {
+            if let Some((prev_piece, prev_to)) = self.previous(ply, back) {
+                score +=
+                    i32::from(self.continuation[prev_piece * 64 + prev_to.to_index()][piece][to]);
+            }
+        }
+
+        score
+    }
+
+    /// Rewards the quiet move that caused a beta cutoff and punishes the quiets tried before it
+    pub fn update_quiets<B: Position>(
+        &mut self,
+        board: &B,
+        best: ChessMove,
+        tried: &[ChessMove],
+        ply: u8,
+        depth: u8,
+    ) {
+        let bonus = (32 * i32::from(depth) * i32::from(depth)).min(1536);
+
+        self.update_quiet(board, best, ply, bonus);
+        for &mv in tried {
+            self.update_quiet(board, mv, ply, -bonus);
+        }
+
+        if let Some((piece, square)) = self.previous(ply, 1) {
+            self.countermoves[piece][square.to_index()] = Some(best);
+        }
+    }
+
+    fn update_quiet<B: Position>(&mut self, board: &B, mv: ChessMove, ply: u8, bonus: i32) {
+        let piece = piece_index(board, mv);
+        let to = mv.get_dest().to_index();
+
+        gravity(
+            &mut self.history[board.side_to_move().to_index()][mv.get_source().to_index()][to],
+            bonus,
+        );
+        for back in [1, 2] {
+            if let Some((prev_piece, prev_to)) = self.previous(ply, back) {
+                let entry = &mut self.continuation[prev_piece * 64 + prev_to.to_index()][piece][to];
+                let mut value = i32::from(*entry);
+                gravity(&mut value, bonus);
+                *entry = value as i16;
+            }
+        }
+    }
+}
+
+/// History update that keeps the entry within `MAX_HISTORY`
+/// <https://www.chessprogramming.org/History_Heuristic>
+fn gravity(entry: &mut i32, bonus: i32) {
+    *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
 }
 
-fn printpv(tt: &TT, board: &Board, current_best: Option<ChessMove>) -> String {
+/// Index of the moving piece including its color, `0..12`
+#[must_use]
+pub fn piece_index<B: Position>(board: &B, mv: ChessMove) -> usize {
+    let piece = board.piece_on(mv.get_source()).map_or(0, |x| x.to_index());
+    board.side_to_move().to_index() * 6 + piece
+}
+
+fn printpv<B: Position>(
+    tt: &TT,
+    board: &B,
+    current_best: Option<ChessMove>,
+    chess960: bool,
+) -> String {
     let mut board = *board;
     let mut pv = Vec::with_capacity(64);
     if let Some(current_best) = current_best {
-        pv.push(current_best);
+        pv.push(board.format_move(current_best, chess960));
         board = board.make_move_new(current_best);
     }
 
@@ -53,7 +171,7 @@ fn printpv(tt: &TT, board: &Board, current_best: Option<ChessMove>) -> String {
         if entry.is_valid(key) && entry.depth >= depth {
             if let Some(mv) = entry.best_move {
                 // dbg!("  Found move: {}", mv);
-                pv.push(mv);
+                pv.push(board.format_move(mv, chess960));
                 board = board.make_move_new(mv);
                 depth += 1;
             } else {
@@ -67,122 +185,299 @@ fn printpv(tt: &TT, board: &Board, current_best: Option<ChessMove>) -> String {
     }
 
     // dbg!("Total PV length: {}", pv.len());
-    pv.iter()
-        .map(std::string::ToString::to_string)
-        .collect::<Vec<String>>()
-        .join(" ")
+    pv.join(" ")
 }
 
 pub const MAX_MOVES: usize = 128;
 
-pub struct MoveGenOrdered {
-    moves: ArrayVec<(ChessMove, i32), MAX_MOVES>,
-    pub len: usize,
-    real_len: usize,
-    board_checkers: u64,
+/// A move with its score and its place in generation order, which breaks ties
+type ScoredMove = (ChessMove, i32, usize);
+
+/// Adds a move behind the ones generated before it
+fn push_scored(moves: &mut ArrayVec<ScoredMove, MAX_MOVES>, mv: ChessMove, score: i32) {
+    moves.push((mv, score, moves.len()));
 }
 
-impl MoveGenOrdered {
+/// Stages of `MoveGenOrdered`, in the order they are searched
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killers,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    GenerateChecks,
+    Checks,
+    Done,
+}
+
+/// Staged move generator.
+/// The hash move is tried before anything is generated, then good captures and queen
+/// promotions, killers, quiets and losing captures and underpromotions last.
+/// With `caponly` only captures and queen promotions are generated, followed by quiet checks
+/// if asked for with `with_checks`.
+pub struct MoveGenOrdered<B: Position> {
+    board: B,
+    stage: Stage,
+    ply: u8,
+    caponly: bool,
+    checks: bool,
+    tt_move: Option<ChessMove>,
+    killers: [Option<ChessMove>; 2],
+    killer_index: usize,
+    /// Scored moves of the current stage
+    moves: ArrayVec<ScoredMove, MAX_MOVES>,
+    bad_captures: ArrayVec<ScoredMove, MAX_MOVES>,
+    /// Number of moves returned so far
+    pub yielded: usize,
+}
+
+impl<B: Position> MoveGenOrdered<B> {
     #[must_use]
     pub fn new(
-        board: &Board,
+        board: &B,
         sinfo: &SearchInfo,
         ply: u8,
         tt_move: Option<ChessMove>,
         caponly: bool,
     ) -> Self {
-        let mut moves = ArrayVec::new();
-
-        let movegen = MoveGen::new_legal(board);
+        Self {
+            board: *board,
+            stage: if caponly {
+                Stage::GenerateCaptures
+            } else {
+                Stage::HashMove
+            },
+            ply,
+            caponly,
+            checks: false,
+            tt_move: if caponly { None } else { tt_move },
+            killers: [
+                sinfo.killers[0][ply as usize],
+                sinfo.killers[1][ply as usize],
+            ],
+            killer_index: 0,
+            moves: ArrayVec::new(),
+            bad_captures: ArrayVec::new(),
+            yielded: 0,
+        }
+    }
 
-        // If it's capture only, make the target squares the opponents squares
-        // otherwise it's all squares
-        let targets = if caponly {
-            *board.color_combined(!board.side_to_move())
-        } else {
-            !chess::EMPTY
-        };
+    /// Also generates quiet moves that give check after the captures
+    #[must_use]
+    pub fn with_checks(mut self) -> Self {
+        self.checks = true;
+        self
+    }
 
-        // keep track of the real length without the target for status checking
-        let mut real_len = 0;
+    /// Whether the position has no legal moves at all, only meaningful once all moves were picked
+    pub fn no_legal_moves(&mut self) -> bool {
+        self.yielded == 0 && !self.board.has_legal_moves()
+    }
 
-        for mv in movegen {
-            if (BitBoard::from_square(mv.get_dest()) & targets).0 != 0 {
-                let score = score_move(mv, board, sinfo, ply, tt_move);
-                moves.push((mv, score as i32));
-            }
+    /// Picks the highest scored move of the current stage, equal scores in generation order
+    fn pick_best(&mut self) -> Option<ChessMove> {
+        let best_index = self
+            .moves
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (_, score, order))| (*score, Reverse(*order)))?
+            .0;
 
-            real_len += 1;
-        }
-
-        Self {
-            len: moves.len(),
-            real_len,
-            moves,
-            board_checkers: board.checkers().0,
-        }
+        Some(self.moves.swap_remove(best_index).0)
     }
 
-    #[must_use]
-    pub fn status(&self) -> BoardStatus {
-        match self.real_len {
-            0 => {
-                if self.board_checkers == 0 {
-                    BoardStatus::Stalemate
-                } else {
-                    BoardStatus::Checkmate
-                }
-            }
-            _ => BoardStatus::Ongoing,
+    pub fn pick_next(&mut self, sinfo: &SearchInfo) -> Option<ChessMove> {
+        let mv = self.next_stage_move(sinfo);
+        if mv.is_some() {
+            self.yielded += 1;
         }
+        mv
     }
 
-    pub fn pick_next(&mut self) -> Option<ChessMove> {
-        let mut best_mv = None;
-        let mut best_score = -1;
-        let mut best_index = 0;
-
-        for mv_i in 0..self.moves.len() {
-            let mv = self.moves[mv_i];
-            if mv.1 > best_score {
-                best_mv = Some(mv.0);
-                best_score = mv.1;
-                best_index = mv_i;
+    fn next_stage_move(&mut self, sinfo: &SearchInfo) -> Option<ChessMove> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    match self.tt_move {
+                        Some(mv) if is_legal(&self.board, mv) => return Some(mv),
+                        _ => self.tt_move = None,
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    let mut captures = MoveList::new();
+                    self.board.generate_captures(&mut captures);
+
+                    for mv in captures {
+                        if self.tt_move == Some(mv) {
+                            continue;
+                        }
+                        let score = capture_score(&self.board, mv);
+                        if is_bad_capture(&self.board, mv) {
+                            push_scored(&mut self.bad_captures, mv, score);
+                        } else {
+                            push_scored(&mut self.moves, mv, score);
+                        }
+                    }
+
+                    // Quiet queen promotions are as good as a capture, underpromotions stay quiets
+                    let us = self.board.side_to_move();
+                    let pawns = *self.board.pieces(Piece::Pawn)
+                        & *self.board.color_combined(us)
+                        & get_rank(us.to_seventh_rank());
+                    for source in pawns {
+                        let dest = source.uforward(us);
+                        let mv = ChessMove::new(source, dest, Some(Piece::Queen));
+                        if self.tt_move != Some(mv)
+                            && self.board.piece_on(dest).is_none()
+                            && is_legal(&self.board, mv)
+                        {
+                            push_scored(&mut self.moves, mv, PROMOTION_VALUE);
+                        }
+                    }
+
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    if let Some(mv) = self.pick_best() {
+                        return Some(mv);
+                    }
+                    if self.caponly {
+                        self.moves = std::mem::take(&mut self.bad_captures);
+                        self.stage = Stage::BadCaptures;
+                    } else {
+                        self.stage = Stage::Killers;
+                    }
+                }
+                Stage::Killers => {
+                    // Killers come from sibling positions, so they have to be checked
+                    while self.killer_index < self.killers.len() {
+                        let i = self.killer_index;
+                        self.killer_index += 1;
+
+                        if let Some(mv) = self.killers[i] {
+                            if self.tt_move != Some(mv)
+                                && (i == 0 || self.killers[0] != Some(mv))
+                                && !self.board.is_capture(mv)
+                                && mv.get_promotion().is_none()
+                                && is_legal(&self.board, mv)
+                            {
+                                return Some(mv);
+                            }
+                        }
+                    }
+                    self.stage = Stage::GenerateQuiets;
+                }
+                Stage::GenerateQuiets => {
+                    let mut quiets = MoveList::new();
+                    self.board.generate_quiets(&mut quiets);
+
+                    for mv in quiets {
+                        if !(self.tt_move == Some(mv)
+                            || self.killers.contains(&Some(mv))
+                            || mv.get_promotion() == Some(Piece::Queen))
+                        {
+                            let score = quiet_score(&self.board, sinfo, self.ply, mv);
+                            push_scored(&mut self.moves, mv, score);
+                        }
+                    }
+
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    if let Some(mv) = self.pick_best() {
+                        return Some(mv);
+                    }
+                    self.moves = std::mem::take(&mut self.bad_captures);
+                    self.stage = Stage::BadCaptures;
+                }
+                Stage::BadCaptures => {
+                    if let Some(mv) = self.pick_best() {
+                        return Some(mv);
+                    }
+                    self.stage = if self.caponly && self.checks {
+                        Stage::GenerateChecks
+                    } else {
+                        Stage::Done
+                    };
+                }
+                Stage::GenerateChecks => {
+                    let mut quiets = MoveList::new();
+                    self.board.generate_quiets(&mut quiets);
+
+                    for mv in quiets {
+                        if mv.get_promotion().is_none()
+                            && *self.board.make_move_new(mv).checkers() != chess::EMPTY
+                        {
+                            push_scored(&mut self.moves, mv, 0);
+                        }
+                    }
+
+                    self.stage = Stage::Checks;
+                }
+                Stage::Checks => {
+                    if let Some(mv) = self.pick_best() {
+                        return Some(mv);
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
             }
         }
-
-        self.moves.remove(best_index);
-
-        best_mv
     }
 }
 
-pub fn log_search_statistics(
+#[allow(clippy::too_many_arguments)]
+pub fn log_search_statistics<B: Position>(
     depth: u8,
     best_score: i32,
     start: &Instant,
     _sinfo: &SearchInfo,
-    board: &Board,
+    board: &B,
     tt: &TT,
     bestmv: Option<ChessMove>,
+    chess960: bool,
+    xboard: bool,
 ) {
     /*let who2move = match board.side_to_move() {
         chess::Color::White => 1,
         chess::Color::Black => -1,
     };*/
-    unsafe {
-        let time = Instant::now().duration_since(*start).as_millis();
-        println!(
-            "info score cp {} depth {depth} nodes {NODES_SEARCHED} qnodes {QNODES_SEARCHED} time {time} pv {}",
-            best_score,
-            printpv(tt, board, bestmv),
-        );
+    let (nodes, qnodes, checkexts, singular, multicut, tt_check, tt_hit) = (
+        NODES_SEARCHED.get(),
+        QNODES_SEARCHED.get(),
+        CHECK_EXTENSION.get(),
+        SINGULAR_EXTENSION.get(),
+        MULTI_CUT.get(),
+        TT_CHECK.get(),
+        TT_HIT.get(),
+    );
+    let time = Instant::now().duration_since(*start).as_millis();
+
+    // CECP thinking output: ply, score, time in centiseconds, nodes and the PV
+    if xboard {
         println!(
-            "info string checkexts {CHECK_EXTENSION} EBR {} TT Check {TT_CHECK} hit {TT_HIT} nps {:.0}",
-            (NODES_SEARCHED as f64).powf(1. / f64::from(depth)),
-            (1000 * NODES_SEARCHED as u128) / (time + 1)
+            "{depth} {} {} {nodes} {}",
+            cecp_score(best_score),
+            time / 10,
+            printpv(tt, board, bestmv, chess960),
         );
+        return;
     }
+
+    println!(
+        "info score cp {} depth {depth} nodes {nodes} qnodes {qnodes} time {time} pv {}",
+        best_score,
+        printpv(tt, board, bestmv, chess960),
+    );
+    println!(
+        "info string checkexts {checkexts} singular {singular} multicut {multicut} EBR {} TT Check {tt_check} hit {tt_hit} nps {:.0}",
+        (nodes as f64).powf(1. / f64::from(depth)),
+        (1000 * nodes as u128) / (time + 1)
+    );
 }
 
 pub const MVV_LVA: [[u8; chess::NUM_PIECES + 1]; chess::NUM_PIECES + 1] = [
@@ -200,46 +495,126 @@ fn piece_to_index(a: Option<Piece>) -> usize {
     a.map_or(0, |a| a.to_index() + 1)
 }
 
-// TODO Tune this
-const HASH_VALUE: u32 = 50;
-const KILLER_VALUE: u32 = 20;
+/// MVV-LVA score of a capture
+fn capture_score<B: Position>(b: &B, mv: ChessMove) -> i32 {
+    let attacker = piece_to_index(b.piece_on(mv.get_source()));
+    let victim = piece_to_index(b.piece_on(mv.get_dest()));
+
+    i32::from(MVV_LVA[victim][attacker])
+}
 
-fn score_move(
-    mv: ChessMove,
-    b: &Board,
-    sinfo: &SearchInfo,
-    ply: u8,
-    hash: Option<ChessMove>,
-) -> u32 {
-    // Check if move is best move indicated by TT
-    if hash == Some(mv) {
-        return HASH_VALUE;
+/// Quiets are ordered by countermove and history, or left in generation order
+fn quiet_score<B: Position>(b: &B, sinfo: &SearchInfo, ply: u8, mv: ChessMove) -> i32 {
+    if !HISTORY_ORDERING.get() {
+        return 0;
+    }
+    if sinfo.countermove(ply) == Some(mv) {
+        return COUNTERMOVE_VALUE;
     }
 
-    let attacker = piece_to_index(b.piece_on(mv.get_source()));
-    let victim = piece_to_index(b.piece_on(mv.get_dest()));
+    sinfo.quiet_history(b, mv, ply)
+}
+
+/// Rough piece values to find losing captures
+const CAPTURE_VALUES: [i32; 6] = [1, 3, 3, 5, 9, 100];
+
+/// A capture of a cheaper piece on a defended square
+fn is_bad_capture<B: Position>(b: &B, mv: ChessMove) -> bool {
+    let (Some(attacker), Some(victim)) = (b.piece_on(mv.get_source()), b.piece_on(mv.get_dest()))
+    else {
+        return false;
+    };
+
+    CAPTURE_VALUES[attacker.to_index()] > CAPTURE_VALUES[victim.to_index()]
+        && is_attacked(
+            b,
+            mv.get_dest(),
+            !b.side_to_move(),
+            *b.combined() ^ BitBoard::from_square(mv.get_source()),
+        )
+}
+
+/// Whether `by` attacks `square` with the given occupancy
+fn is_attacked<B: Position>(b: &B, square: Square, by: Color, occupied: BitBoard) -> bool {
+    let them = *b.color_combined(by);
+    let queens = *b.pieces(Piece::Queen);
+
+    (get_knight_moves(square) & *b.pieces(Piece::Knight) & them
+        | get_king_moves(square) & *b.pieces(Piece::King) & them
+        | get_pawn_attacks(square, !by, *b.pieces(Piece::Pawn) & them)
+        | get_bishop_moves(square, occupied) & (*b.pieces(Piece::Bishop) | queens) & them
+        | get_rook_moves(square, occupied) & (*b.pieces(Piece::Rook) | queens) & them)
+        != chess::EMPTY
+}
 
-    let mvv_lva = u32::from(MVV_LVA[victim][attacker]);
+/// Legality check for moves that did not come from the move generator, like hash moves and
+/// killers, without generating all moves
+fn is_legal<B: Position>(b: &B, mv: ChessMove) -> bool {
+    let us = b.side_to_move();
+    let (source, dest) = (mv.get_source(), mv.get_dest());
+    let Some(piece) = b.piece_on(source) else {
+        return false;
+    };
+    let file_distance = source
+        .get_file()
+        .to_index()
+        .abs_diff(dest.get_file().to_index());
+
+    // Castling and en passant are rare enough to leave to the move generator
+    if b.is_castling(mv)
+        || (piece == Piece::Pawn && file_distance == 1 && b.piece_on(dest).is_none())
+    {
+        return b.legal(mv);
+    }
 
-    // If it's a capture, return MVV-LVA score
-    if mvv_lva > 0 {
-        return mvv_lva;
+    if b.color_on(source) != Some(us)
+        || b.color_on(dest) == Some(us)
+        || b.piece_on(dest) == Some(Piece::King)
+    {
+        return false;
     }
 
-    // Check if the move is a killer move
-    if sinfo.killers[0][ply as usize] == Some(mv) {
-        return KILLER_VALUE;
+    let occupied = *b.combined();
+    let dest_bb = BitBoard::from_square(dest);
+
+    let pseudo_legal = match piece {
+        Piece::Pawn => {
+            let promotes = dest.get_rank() == us.to_their_backrank();
+            get_pawn_moves(source, us, occupied) & dest_bb != chess::EMPTY
+                && promotes == mv.get_promotion().is_some()
+                && mv.get_promotion() != Some(Piece::Pawn)
+                && mv.get_promotion() != Some(Piece::King)
+        }
+        Piece::Knight => get_knight_moves(source) & dest_bb != chess::EMPTY,
+        Piece::Bishop => get_bishop_moves(source, occupied) & dest_bb != chess::EMPTY,
+        Piece::Rook => get_rook_moves(source, occupied) & dest_bb != chess::EMPTY,
+        Piece::Queen => {
+            (get_bishop_moves(source, occupied) | get_rook_moves(source, occupied)) & dest_bb
+                != chess::EMPTY
+        }
+        Piece::King => get_king_moves(source) & dest_bb != chess::EMPTY,
+    };
+    if !pseudo_legal || (piece != Piece::Pawn && mv.get_promotion().is_some()) {
+        return false;
     }
-    if sinfo.killers[1][ply as usize] == Some(mv) {
-        return KILLER_VALUE - 10;
+
+    if piece == Piece::King {
+        return !is_attacked(
+            b,
+            dest,
+            !us,
+            occupied ^ BitBoard::from_square(source) | dest_bb,
+        );
     }
 
-    // Otherwise, return the history score
-    // Through testing it checks less nodes but is slower overall
-    // TODO fix this
-    // sinfo.history[mv.get_source().to_index()][mv.get_dest().to_index()]
+    if b.checkers().0 != 0 {
+        let after = b.make_move_new(mv);
+        return !is_attacked(&after, after.king_square(us), !us, *after.combined());
+    }
 
-    mvv_lva
+    // Pinned pieces may only move along the pin
+    b.pinned() & BitBoard::from_square(source) == chess::EMPTY
+        || line(source, b.king_square(us)) & dest_bb != chess::EMPTY
 }
 
 // #[must_use] pub fn sort_moves(
@@ -261,6 +636,29 @@ pub const fn is_mate_score(score: i32) -> bool {
     score >= MATE_SCORE || score <= -MATE_SCORE
 }
 
+/// Moves to mate of a mate score, negative when the side to move gets mated
+#[must_use]
+pub const fn mate_in(score: i32) -> Option<i32> {
+    if !is_mate_score(score) {
+        None
+    } else if score > 0 {
+        Some((OO - score + 1) / 2)
+    } else {
+        Some(-(OO + score) / 2)
+    }
+}
+
+/// Score in CECP thinking output, where mate in N moves is 100000 + N and being mated in N
+/// moves is -100000 - N
+#[must_use]
+pub const fn cecp_score(score: i32) -> i32 {
+    match mate_in(score) {
+        Some(moves) if moves > 0 => 100_000 + moves,
+        Some(moves) => -100_000 + moves,
+        None => score,
+    }
+}
+
 /// Persistent data between games
 #[derive(Debug, Clone, Copy)]
 pub struct History {
@@ -342,4 +740,126 @@ mod tests {
     }
 
     // 1R6/5p2/8/1k1r4/3B4/P2PKP2/1P6/2R5 b - - 15 53
+
+    #[test]
+    fn test_mate_scores() {
+        use crate::engine::OO;
+        use crate::utils::{cecp_score, mate_in};
+
+        // Mate in one is one ply away, being mated in one is two plies away
+        assert_eq!(mate_in(OO - 1), Some(1));
+        assert_eq!(mate_in(OO - 3), Some(2));
+        assert_eq!(mate_in(-OO + 2), Some(-1));
+        assert_eq!(mate_in(150), None);
+        assert_eq!(cecp_score(OO - 3), 100_002);
+        assert_eq!(cecp_score(-OO + 2), -100_001);
+        assert_eq!(cecp_score(-150), -150);
+    }
+
+    #[test]
+    fn test_quiet_history() {
+        use crate::board::Board;
+        use crate::utils::{piece_index, SearchInfo, MAX_HISTORY};
+        use chess::ChessMove;
+        use std::str::FromStr;
+
+        let board = Board::default();
+        let good = ChessMove::from_str("g1f3").unwrap();
+        let bad = ChessMove::from_str("a2a3").unwrap();
+
+        let mut sinfo = SearchInfo::default();
+        sinfo.stack[0] = Some((piece_index(&board, good), good.get_dest()));
+
+        for _ in 0..1000 {
+            sinfo.update_quiets(&board, good, &[bad], 1, 20);
+        }
+
+        // Gravity keeps the scores bounded
+        let good_score = sinfo.quiet_history(&board, good, 1);
+        let bad_score = sinfo.quiet_history(&board, bad, 1);
+        assert!((1..=2 * MAX_HISTORY).contains(&good_score));
+        assert!((-2 * MAX_HISTORY..0).contains(&bad_score));
+        assert_eq!(sinfo.countermove(1), Some(good));
+        assert_eq!(sinfo.countermove(0), None);
+    }
+
+    #[test]
+    fn test_staged_movegen() {
+        use crate::board::Board;
+        use crate::utils::{is_legal, MoveGenOrdered, SearchInfo};
+        use chess::{ChessMove, Piece, EMPTY};
+        use std::str::FromStr;
+
+        let mut sinfo = SearchInfo::default();
+        let mut candidates: Vec<ChessMove> = vec![];
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/3pP3/8/8/8/4K2R w K d6 0 1",
+            "4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/3q4/4K3 w - - 0 1",
+            "8/1P4k1/8/8/8/8/5p2/4K2R w - - 0 1",
+            "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/RR2K1R1 w KBk - 0 1",
+        ] {
+            let board = Board::from_str(fen).unwrap();
+            let legal: Vec<ChessMove> = board.legal_moves().to_vec();
+
+            // Moves of other positions make for illegal hash moves and killers
+            for &mv in &candidates {
+                assert_eq!(is_legal(&board, mv), board.legal(mv), "{mv} on {fen}");
+            }
+            candidates.extend(&legal);
+
+            for (i, &tt_move) in candidates.iter().enumerate().step_by(7) {
+                sinfo.killers[0][1] = candidates.get(i + 1).copied();
+                sinfo.killers[1][1] = candidates.get(i + 2).copied();
+
+                let mut movegen = MoveGenOrdered::new(&board, &sinfo, 1, Some(tt_move), false);
+                let mut moves = vec![];
+                while let Some(mv) = movegen.pick_next(&sinfo) {
+                    moves.push(mv);
+                }
+                if board.legal(tt_move) {
+                    assert_eq!(moves[0], tt_move);
+                }
+
+                let mut expected = legal.clone();
+                moves.sort_by_key(ToString::to_string);
+                expected.sort_by_key(ToString::to_string);
+                assert_eq!(moves, expected, "{fen}");
+            }
+
+            // Captures and queen promotions only, then quiet checks
+            let tactical =
+                |mv: &ChessMove| board.is_capture(*mv) || mv.get_promotion() == Some(Piece::Queen);
+            let gives_check = |mv: &ChessMove| *board.make_move_new(*mv).checkers() != EMPTY;
+
+            let mut movegen = MoveGenOrdered::new(&board, &sinfo, 1, None, true);
+            let mut captures = 0;
+            while let Some(mv) = movegen.pick_next(&sinfo) {
+                assert!(tactical(&mv));
+                captures += 1;
+            }
+            assert_eq!(
+                captures,
+                legal.iter().filter(|mv| tactical(mv)).count(),
+                "{fen}"
+            );
+
+            let mut movegen = MoveGenOrdered::new(&board, &sinfo, 1, None, true).with_checks();
+            let mut moves = vec![];
+            while let Some(mv) = movegen.pick_next(&sinfo) {
+                moves.push(mv);
+            }
+            let checks = legal
+                .iter()
+                .filter(|mv| !tactical(mv) && mv.get_promotion().is_none() && gives_check(mv))
+                .count();
+            assert_eq!(moves.len(), captures + checks, "{fen}");
+            assert!(moves[captures..].iter().all(gives_check));
+        }
+    }
 }
@@ -0,0 +1,411 @@
+//! CECP (XBoard/WinBoard) front end
+//! <https://www.gnu.org/software/xboard/engine-intf.html>
+//!
+//! `main` hands the input over when the first command is `xboard` or `protover`. Moves are
+//! written in coordinate notation like `e7e8q`. Input is read on its own thread, so `analyze`
+//! searches until the next command. Thinking on a move is not interrupted.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use chess::{ChessMove, Color};
+
+use crate::board::Board;
+use crate::engine::Engine;
+use crate::time::TimeManager;
+use crate::utils::History;
+
+/// Commands read on another thread, so that they can stop the analysis
+struct Input {
+    lines: mpsc::Receiver<String>,
+    /// Lines taken from the channel while checking for input
+    pending: VecDeque<String>,
+    /// Set after every line
+    stop: Arc<AtomicBool>,
+}
+
+impl Input {
+    /// Reads the lines of `lines()` on a new thread, which opens the input there
+    fn spawn<I: Iterator<Item = String>>(lines: impl FnOnce() -> I + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        thread::spawn(move || {
+            for line in lines() {
+                if sender.send(line).is_err() {
+                    return;
+                }
+                flag.store(true, Ordering::SeqCst);
+            }
+        });
+
+        Self {
+            lines: receiver,
+            pending: VecDeque::new(),
+            stop,
+        }
+    }
+
+    fn next(&mut self) -> Option<String> {
+        self.pending.pop_front().or_else(|| self.lines.recv().ok())
+    }
+
+    /// Clears the stop flag, false when a command is already waiting.
+    /// A line sent after the check sets the flag again.
+    fn idle(&mut self) -> bool {
+        self.stop.store(false, Ordering::SeqCst);
+        self.pending.extend(self.lines.try_iter());
+        self.pending.is_empty()
+    }
+}
+
+pub struct XBoard {
+    board: Board,
+    history: History,
+    /// Positions before each move, for `undo` and `remove`
+    played: Vec<(Board, History)>,
+    /// Side the engine plays, none in force mode
+    engine_side: Option<Color>,
+    post: bool,
+    analyze: bool,
+    /// Engine's clock in ms, from `level` and then `time`
+    clock: Option<u32>,
+    /// From `level`, in ms
+    increment: u32,
+    /// Moves until the clock is refilled, from `level`, none when it never is
+    moves_per_session: Option<u32>,
+    /// From `st`, in ms
+    move_time: Option<u32>,
+    /// From `sd`
+    max_depth: Option<u8>,
+    /// None when commands are passed in directly, then analysis is limited like a normal search
+    input: Option<Input>,
+}
+
+impl Default for XBoard {
+    fn default() -> Self {
+        Self {
+            board: Board::default(),
+            history: History::new(),
+            played: Vec::new(),
+            engine_side: Some(Color::Black),
+            post: false,
+            analyze: false,
+            clock: None,
+            increment: 0,
+            moves_per_session: None,
+            move_time: None,
+            max_depth: None,
+            input: None,
+        }
+    }
+}
+
+impl XBoard {
+    /// Handles one command, returns false on `quit`
+    pub fn command(
+        &mut self,
+        line: &str,
+        engine: &mut Engine,
+        new_engine: impl Fn() -> Engine,
+    ) -> bool {
+        let (command, args) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let args = args.trim();
+
+        match command {
+            "xboard" | "" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+            | "name" | "rating" | "ics" | "." => {}
+            "protover" => println!(
+                "feature myname=\"PiCa v{}\" setboard=1 usermove=1 ping=1 analyze=1 colors=0 \
+                 sigint=0 sigterm=0 reuse=1 done=1",
+                env!("CARGO_PKG_VERSION")
+            ),
+            "quit" => return false,
+            "ping" => println!("pong {args}"),
+            "new" => {
+                *self = Self {
+                    post: self.post,
+                    analyze: self.analyze,
+                    input: self.input.take(),
+                    ..Self::default()
+                };
+                *engine = new_engine();
+            }
+            "setboard" => match Board::from_fen(args) {
+                Ok(board) => {
+                    self.board = board;
+                    self.history = History::new();
+                    self.history.push_hist(board.get_hash());
+                    self.played.clear();
+                }
+                Err(e) => println!("tellusererror Illegal position: {e}"),
+            },
+            "force" | "result" => self.engine_side = None,
+            "go" => {
+                self.engine_side = Some(self.board.side_to_move());
+                self.think(engine);
+            }
+            "usermove" => match self.board.parse_uci(args) {
+                Some(mv) => {
+                    self.play(mv);
+                    if self.engine_side == Some(self.board.side_to_move()) {
+                        self.think(engine);
+                    }
+                }
+                None => println!("Illegal move: {args}"),
+            },
+            "undo" | "remove" => {
+                let count = if command == "undo" { 1 } else { 2 };
+                if count <= self.played.len() {
+                    let at = self.played.len() - count;
+                    (self.board, self.history) = self.played[at];
+                    self.played.truncate(at);
+                }
+            }
+            "level" => match parse_level(args) {
+                Some((moves, base, increment)) => {
+                    self.moves_per_session = (moves > 0).then_some(moves);
+                    self.clock = Some(base);
+                    self.increment = increment;
+                }
+                None => println!("Error (invalid level): {args}"),
+            },
+            "st" => match args.parse::<u32>() {
+                Ok(seconds) => self.move_time = Some(seconds * 1000),
+                Err(_) => println!("Error (invalid time): {args}"),
+            },
+            "sd" => match args.parse() {
+                Ok(depth) => self.max_depth = Some(depth),
+                Err(_) => println!("Error (invalid depth): {args}"),
+            },
+            "time" => match args.parse::<u32>() {
+                Ok(centiseconds) => self.clock = Some(centiseconds * 10),
+                Err(_) => println!("Error (invalid time): {args}"),
+            },
+            // The time manager only uses the engine's clock
+            "otim" => {}
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "analyze" => {
+                self.analyze = true;
+                self.engine_side = None;
+            }
+            "exit" => self.analyze = false,
+            _ => println!("Error (unknown command): {command}"),
+        }
+
+        // Any command stops the analysis, so it starts over after each one
+        if self.analyze {
+            self.analyze(engine);
+        }
+
+        true
+    }
+
+    /// Searches the position until the next command
+    fn analyze(&mut self, engine: &mut Engine) {
+        if !self.board.has_legal_moves() {
+            return;
+        }
+
+        let time = match &mut self.input {
+            Some(input) => {
+                if !input.idle() {
+                    return;
+                }
+                TimeManager {
+                    board_time: Some(u32::MAX),
+                    stop: Some(Arc::clone(&input.stop)),
+                    ..Default::default()
+                }
+            }
+            None => TimeManager::default(),
+        };
+        self.search(engine, &time);
+    }
+
+    /// Searches and plays a move for the side to move
+    fn think(&mut self, engine: &mut Engine) {
+        if !self.board.has_legal_moves() {
+            self.report_result();
+            return;
+        }
+
+        let time = match self.move_time {
+            Some(move_time) => TimeManager {
+                max_depth: self.max_depth,
+                max_allowed_time_now: Some(move_time),
+                ..Default::default()
+            },
+            None => TimeManager {
+                max_depth: self.max_depth,
+                board_time: self.clock,
+                increment: Some(self.increment),
+                // Moves made in this session, counting from the first move of the game
+                moves_to_go: self
+                    .moves_per_session
+                    .map(|moves| moves - (u32::from(self.board.fullmove_number()) - 1) % moves),
+                ..Default::default()
+            },
+        };
+        let mv = self.search(engine, &time);
+        println!("move {}", self.board.format_move(mv, false));
+        self.play(mv);
+        self.report_result();
+    }
+
+    fn search(&self, engine: &mut Engine, time: &TimeManager) -> ChessMove {
+        engine.info = self.post || self.analyze;
+        engine.xboard = true;
+        engine.chess960 = false;
+        engine.start(self.board, time, self.history)
+    }
+
+    fn play(&mut self, mv: ChessMove) {
+        self.played.push((self.board, self.history));
+        self.board.make_move(mv);
+        self.history.push_hist(self.board.get_hash());
+    }
+
+    /// Claims the result once the game is over
+    fn report_result(&mut self) {
+        if self.board.has_legal_moves() {
+            return;
+        }
+        if self.board.checkers().0 == 0 {
+            println!("1/2-1/2 {{Stalemate}}");
+        } else if self.board.side_to_move() == Color::White {
+            println!("0-1 {{Black mates}}");
+        } else {
+            println!("1-0 {{White mates}}");
+        }
+        self.engine_side = None;
+    }
+}
+
+/// Moves per session, base time and increment in ms of `level <moves> <base> <increment>`.
+/// The base is in minutes or `min:sec`, the increment in seconds.
+fn parse_level(args: &str) -> Option<(u32, u32, u32)> {
+    let [moves, base, increment] = args.split_whitespace().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+    let base = (minutes.parse::<u32>().ok()? * 60 + seconds.parse::<u32>().ok()?) * 1000;
+    let increment = increment.parse::<f64>().ok().filter(|x| *x >= 0.)? * 1000.;
+
+    Some((moves.parse().ok()?, base, increment as u32))
+}
+
+/// Runs the protocol until `quit` or the end of input, starting with the detected `first`
+/// command. The rest is read from `lines()`, called on the input thread.
+pub fn run<I: Iterator<Item = String>>(
+    first: &str,
+    lines: impl FnOnce() -> I + Send + 'static,
+    new_engine: impl Fn() -> Engine,
+) {
+    let mut xboard = XBoard {
+        input: Some(Input::spawn(lines)),
+        ..Default::default()
+    };
+    let mut engine = new_engine();
+
+    let mut line = first.to_string();
+    while xboard.command(&line, &mut engine, &new_engine) {
+        match xboard.input.as_mut().and_then(Input::next) {
+            Some(next) => line = next,
+            None => return,
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_commands() {
+        use super::*;
+
+        let new_engine = || Engine::new(1);
+        let mut engine = new_engine();
+        let mut xboard = XBoard::default();
+        let mut send = |xboard: &mut XBoard, line: &str| {
+            assert!(xboard.command(line, &mut engine, new_engine));
+        };
+
+        // The engine plays black after `new` and answers the user's move
+        send(&mut xboard, "new");
+        send(&mut xboard, "sd 2");
+        send(&mut xboard, "usermove e2e4");
+        assert_eq!(xboard.played.len(), 2);
+        assert_eq!(xboard.board.side_to_move(), Color::White);
+
+        // Illegal moves are rejected, forced moves are only played
+        send(&mut xboard, "usermove e2e4");
+        send(&mut xboard, "force");
+        send(&mut xboard, "usermove d2d4");
+        assert_eq!(xboard.played.len(), 3);
+
+        // `go` plays for the side to move, `undo` and `remove` take moves back
+        send(&mut xboard, "go");
+        assert_eq!(xboard.played.len(), 4);
+        assert_eq!(xboard.engine_side, Some(Color::Black));
+        send(&mut xboard, "remove");
+        send(&mut xboard, "undo");
+        assert_eq!(xboard.played.len(), 1);
+        assert_eq!(xboard.board.side_to_move(), Color::Black);
+
+        // Castling in coordinate notation, then mate ends the game
+        send(&mut xboard, "setboard 6k1/5ppp/8/8/8/8/8/4K2R w K - 0 1");
+        send(&mut xboard, "force");
+        send(&mut xboard, "usermove e1g1");
+        assert_eq!(
+            xboard.board.piece_on(chess::Square::F1),
+            Some(chess::Piece::Rook)
+        );
+        send(&mut xboard, "setboard 6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1");
+        send(&mut xboard, "go");
+        assert!(!xboard.board.has_legal_moves());
+        assert_eq!(xboard.engine_side, None);
+
+        // Time controls
+        send(&mut xboard, "level 40 0:30 0");
+        assert_eq!(xboard.clock, Some(30_000));
+        assert_eq!(xboard.moves_per_session, Some(40));
+        send(&mut xboard, "level 0 5 2.5");
+        assert_eq!(xboard.clock, Some(300_000));
+        assert_eq!(xboard.increment, 2500);
+        assert_eq!(xboard.moves_per_session, None);
+        send(&mut xboard, "time 1234");
+        assert_eq!(xboard.clock, Some(12_340));
+        send(&mut xboard, "st 3");
+        assert_eq!(xboard.move_time, Some(3000));
+
+        assert!(!xboard.command("quit", &mut engine, new_engine));
+    }
+
+    #[test]
+    fn test_analyze() {
+        use super::*;
+        use std::time::{Duration, Instant};
+
+        // The analysis runs until the next command comes in on the input thread
+        let delayed = || {
+            std::iter::once(()).map(|()| {
+                thread::sleep(Duration::from_millis(500));
+                "exit".to_string()
+            })
+        };
+        let mut engine = Engine::new(1);
+        let mut xboard = XBoard::default();
+        let start = Instant::now();
+        xboard.input = Some(Input::spawn(delayed));
+
+        assert!(xboard.command("analyze", &mut engine, || Engine::new(1)));
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let input = xboard.input.as_mut().unwrap();
+        assert_eq!(input.next().as_deref(), Some("exit"));
+        assert_eq!(input.next(), None);
+    }
+}